@@ -1,5 +1,27 @@
 #!/bin/bash
 
 set -e
-cargo b --release --target wasm32-unknown-unknown --jobs 1
-wasm-bindgen --no-typescript --out-dir /playground/src/ --target web /playground/target/wasm32-unknown-unknown/release/game.wasm
+# The cargo profile to build with, see the manifests for the available profiles.
+profile=${1:-release}
+# The optional wasm-opt level, e.g. `z` or `3`.
+opt_level=$2
+
+cargo b --profile "$profile" --target wasm32-unknown-unknown --jobs 1
+if [ "$profile" = "debug-assertions" ]; then
+    # Keeps the name section, which panic backtraces are read from.
+    opt_debug="--debuginfo"
+fi
+wasm-bindgen --no-typescript --out-dir /playground/src/ --target web "/playground/target/wasm32-unknown-unknown/$profile/game.wasm"
+
+if [ -n "$opt_level" ]; then
+    # The server reads the original to report the size of each crate.
+    mv /playground/src/game_bg.wasm /playground/src/game_bg.original.wasm
+    wasm-opt "-O$opt_level" $opt_debug \
+        --enable-bulk-memory \
+        --enable-mutable-globals \
+        --enable-nontrapping-float-to-int \
+        --enable-reference-types \
+        --enable-sign-ext \
+        --enable-multivalue \
+        /playground/src/game_bg.original.wasm -o /playground/src/game_bg.wasm
+fi
@@ -0,0 +1,123 @@
+use bevy_app::{App, Last};
+use bevy_ecs::{
+    observer::Trigger,
+    resource::Resource,
+    system::{Commands, ResMut},
+};
+use bevy_log::warn;
+use bevy_render::view::screenshot::{Screenshot, ScreenshotCaptured};
+use bevy_time::TimeUpdateStrategy;
+use std::{cell::Cell, time::Duration};
+use wasm_bindgen::prelude::*;
+
+thread_local! {
+    /// The capture requested by the page, picked up at the end of the next frame.
+    static REQUESTED: Cell<Option<CaptureRequest>> = const { Cell::new(None) };
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// Provided by the page to receive captured frames as RGBA8 pixels.
+    #[wasm_bindgen(js_namespace = learnbevy, js_name = onFrame, catch)]
+    fn on_frame(index: u32, width: u32, height: u32, rgba: Vec<u8>) -> Result<(), JsValue>;
+}
+
+/// Capture the next frame of the primary window.
+///
+/// Unlike [`capture_frames`] this leaves the time step alone.
+#[wasm_bindgen]
+pub fn screenshot() {
+    REQUESTED.set(Some(CaptureRequest {
+        frames: 1,
+        fps: None,
+    }));
+}
+
+/// Capture the next `frames` frames of the primary window.
+///
+/// While capturing, each frame advances the app by exactly `1 / fps` seconds so
+/// the result plays back smoothly at `fps`, no matter how long reading back the
+/// frames takes.
+#[wasm_bindgen]
+pub fn capture_frames(frames: u32, fps: u32) {
+    if frames == 0 {
+        return;
+    }
+    REQUESTED.set(Some(CaptureRequest {
+        frames,
+        fps: Some(fps.max(1)),
+    }));
+}
+
+#[derive(Clone, Copy)]
+struct CaptureRequest {
+    frames: u32,
+    /// The fixed frame rate to advance the app at, or `None` to keep the time strategy.
+    fps: Option<u32>,
+}
+
+#[derive(Resource, Default)]
+struct Capture {
+    remaining: u32,
+    index: u32,
+    /// The time strategy to restore once the capture is finished.
+    previous_strategy: Option<TimeUpdateStrategy>,
+}
+
+pub fn init(app: &mut App) {
+    app.init_resource::<Capture>();
+    app.add_systems(Last, capture);
+}
+
+fn capture(
+    mut capture: ResMut<Capture>,
+    mut strategy: Option<ResMut<TimeUpdateStrategy>>,
+    mut commands: Commands,
+) {
+    if let Some(request) = REQUESTED.take() {
+        capture.remaining = request.frames;
+        capture.index = 0;
+        if let (Some(strategy), Some(fps)) = (strategy.as_deref_mut(), request.fps) {
+            let frame_time = Duration::from_secs(1) / fps;
+            let previous =
+                std::mem::replace(strategy, TimeUpdateStrategy::ManualDuration(frame_time));
+            // Keep the original strategy if a capture is requested while capturing.
+            capture.previous_strategy.get_or_insert(previous);
+        }
+    }
+
+    if capture.remaining == 0 {
+        return;
+    }
+
+    let index = capture.index;
+    commands
+        .spawn(Screenshot::primary_window())
+        .observe(move |trigger: Trigger<ScreenshotCaptured>| send_frame(index, trigger));
+    capture.index += 1;
+    capture.remaining -= 1;
+
+    if capture.remaining == 0
+        && let (Some(strategy), Some(previous)) =
+            (strategy.as_deref_mut(), capture.previous_strategy.take())
+    {
+        *strategy = previous;
+    }
+}
+
+fn send_frame(index: u32, trigger: Trigger<ScreenshotCaptured>) {
+    let image = trigger.event().0.clone();
+    let (width, height) = (image.width(), image.height());
+    let mut rgba = match image.try_into_dynamic() {
+        Ok(image) => image.to_rgba8().into_raw(),
+        Err(error) => {
+            warn!("Failed to read captured frame: {error}");
+            return;
+        }
+    };
+    // The alpha channel holds brightness values when HDR is enabled.
+    for pixel in rgba.chunks_exact_mut(4) {
+        pixel[3] = u8::MAX;
+    }
+    let _ = on_frame(index, width, height, rgba);
+}
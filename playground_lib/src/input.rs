@@ -0,0 +1,188 @@
+use crate::trace;
+use bevy_app::{App, PreUpdate};
+use bevy_ecs::{
+    entity::Entity,
+    event::{Event, EventReader, Events},
+    query::With,
+    schedule::IntoScheduleConfigs,
+    world::World,
+};
+use bevy_input::{
+    InputSystem,
+    keyboard::KeyboardInput,
+    mouse::{MouseButtonInput, MouseMotion, MouseWheel},
+    touch::TouchInput,
+};
+use bevy_log::warn;
+use bevy_time::TimeUpdateStrategy;
+use bevy_window::{CursorMoved, PrimaryWindow};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::VecDeque, time::Duration};
+use wasm_bindgen::prelude::*;
+
+/// How much time passes each frame while recording or replaying,
+/// so the run doesn't depend on the frame rate of the browser.
+const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+thread_local! {
+    static MODE: RefCell<Mode> = const { RefCell::new(Mode::Off) };
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// Provided by the page to replay a recording made with [`take_input_recording`].
+    #[wasm_bindgen(js_namespace = learnbevy, js_name = inputReplay, catch)]
+    fn host_input_replay() -> Result<Option<String>, JsValue>;
+    /// Provided by the page to record the input of this run.
+    #[wasm_bindgen(js_namespace = learnbevy, js_name = recordInput, catch)]
+    fn host_record_input() -> Result<bool, JsValue>;
+}
+
+/// Stop recording input and get the recording as JSON.
+///
+/// Returns `undefined` if input isn't being recorded.
+#[wasm_bindgen]
+pub fn take_input_recording() -> Option<String> {
+    let events = MODE.with_borrow_mut(|mode| match std::mem::take(mode) {
+        Mode::Recording(events) => Some(events),
+        other => {
+            *mode = other;
+            None
+        }
+    })?;
+    serde_json::to_string(&events).ok()
+}
+
+/// Check if every event of the replay has been sent.
+#[wasm_bindgen]
+pub fn input_replay_finished() -> bool {
+    MODE.with_borrow(|mode| !matches!(mode, Mode::Replaying(events) if !events.is_empty()))
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Off,
+    Recording(Vec<RecordedEvent>),
+    Replaying(VecDeque<RecordedEvent>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedEvent {
+    frame: u32,
+    event: InputEvent,
+}
+
+#[derive(Serialize, Deserialize)]
+enum InputEvent {
+    Keyboard(KeyboardInput),
+    MouseButton(MouseButtonInput),
+    MouseMotion(MouseMotion),
+    MouseWheel(MouseWheel),
+    CursorMoved(CursorMoved),
+    Touch(TouchInput),
+}
+
+pub fn init(app: &mut App) {
+    let mode = match host_input_replay() {
+        Ok(Some(json)) => match serde_json::from_str(&json) {
+            Ok(events) => Mode::Replaying(events),
+            Err(error) => {
+                warn!("Failed to parse input recording, using real input: {error}");
+                Mode::Off
+            }
+        },
+        _ if host_record_input().unwrap_or(false) => Mode::Recording(Vec::new()),
+        _ => Mode::Off,
+    };
+
+    if matches!(mode, Mode::Off) {
+        return;
+    }
+
+    MODE.set(mode);
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME_TIME));
+    app.add_systems(
+        PreUpdate,
+        (replay_input, record_input).chain().before(InputSystem),
+    );
+}
+
+/// Replace this frame's input events with the recorded ones.
+fn replay_input(world: &mut World) {
+    let frame = trace::frame();
+    let Some(events) = MODE.with_borrow_mut(|mode| {
+        let Mode::Replaying(recorded) = mode else {
+            return None;
+        };
+        let count = recorded.iter().take_while(|e| e.frame <= frame).count();
+        Some(recorded.drain(..count).map(|e| e.event).collect::<Vec<_>>())
+    }) else {
+        return;
+    };
+
+    clear::<KeyboardInput>(world);
+    clear::<MouseButtonInput>(world);
+    clear::<MouseMotion>(world);
+    clear::<MouseWheel>(world);
+    clear::<CursorMoved>(world);
+    clear::<TouchInput>(world);
+
+    // The window entity may not match the one in the recording.
+    let Some(window) = world
+        .query_filtered::<Entity, With<PrimaryWindow>>()
+        .iter(world)
+        .next()
+    else {
+        return;
+    };
+
+    for event in events {
+        match event {
+            InputEvent::Keyboard(e) => send(world, KeyboardInput { window, ..e }),
+            InputEvent::MouseButton(e) => send(world, MouseButtonInput { window, ..e }),
+            InputEvent::MouseMotion(e) => send(world, e),
+            InputEvent::MouseWheel(e) => send(world, MouseWheel { window, ..e }),
+            InputEvent::CursorMoved(e) => send(world, CursorMoved { window, ..e }),
+            InputEvent::Touch(e) => send(world, TouchInput { window, ..e }),
+        }
+    }
+}
+
+fn clear<E: Event>(world: &mut World) {
+    if let Some(mut events) = world.get_resource_mut::<Events<E>>() {
+        events.clear();
+    }
+}
+
+fn send<E: Event>(world: &mut World, event: E) {
+    if let Some(mut events) = world.get_resource_mut::<Events<E>>() {
+        events.send(event);
+    }
+}
+
+fn record_input(
+    mut keyboard: EventReader<KeyboardInput>,
+    mut mouse_button: EventReader<MouseButtonInput>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    mut touch: EventReader<TouchInput>,
+) {
+    MODE.with_borrow_mut(|mode| {
+        let Mode::Recording(recorded) = mode else {
+            return;
+        };
+        let frame = trace::frame();
+        let events = keyboard
+            .read()
+            .cloned()
+            .map(InputEvent::Keyboard)
+            .chain(mouse_button.read().cloned().map(InputEvent::MouseButton))
+            .chain(mouse_motion.read().cloned().map(InputEvent::MouseMotion))
+            .chain(mouse_wheel.read().cloned().map(InputEvent::MouseWheel))
+            .chain(cursor_moved.read().cloned().map(InputEvent::CursorMoved))
+            .chain(touch.read().cloned().map(InputEvent::Touch));
+        recorded.extend(events.map(|event| RecordedEvent { frame, event }));
+    });
+}
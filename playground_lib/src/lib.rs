@@ -1,7 +1,13 @@
-use bevy_app::{App, Update};
+use bevy_app::{App, First};
 
+mod capture;
 mod exit;
+mod input;
 mod log;
+mod panic;
+mod rng;
+mod time;
+mod trace;
 
 /// Items that should be accessable from the sandbox app.
 ///
@@ -9,11 +15,89 @@ mod log;
 /// ```rs
 /// use playground_lib::exports::*;
 /// ```
-pub mod exports {}
+pub mod exports {
+    pub use crate::rng::{PlaygroundRng, SystemRng};
+}
+
+pub struct Plugin {
+    /// The default log filter, using the same syntax as `RUST_LOG`.
+    pub log_filter: &'static str,
+    /// The seed for the [`PlaygroundRng`](rng::PlaygroundRng), see [`rng::resolve_seed`].
+    pub seed: Option<u64>,
+    /// Record a span for every system that runs, so panics can report the running system.
+    ///
+    /// This has a cost every frame, so it's off unless asked for.
+    pub system_spans: bool,
+    /// For games that run outside the playground, which leaves logging and
+    /// panics to Bevy rather than sending them to the page.
+    pub standalone: bool,
+}
+
+impl Default for Plugin {
+    fn default() -> Self {
+        Self {
+            log_filter: trace::DEFAULT_FILTER,
+            seed: None,
+            system_spans: false,
+            standalone: false,
+        }
+    }
+}
 
-pub struct Plugin;
 impl bevy_app::Plugin for Plugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, exit::check_exit_flag);
+        if !self.standalone {
+            trace::init(self.log_filter, self.system_spans);
+        }
+        app.insert_resource(rng::PlaygroundRng::new(rng::resolve_seed(self.seed)));
+        input::init(app);
+        capture::init(app);
+        time::init(app);
+        app.add_systems(First, trace::advance_frame);
+        // This doesn't use `Update` so the app can exit while paused.
+        app.add_systems(First, exit::check_exit_flag);
+    }
+
+    fn finish(&self, _app: &mut App) {
+        // This is added before `DefaultPlugins`, whose `PanicHandlerPlugin` sets
+        // its own hook on the web, so the hook is set once every plugin is built.
+        if !self.standalone {
+            panic::init();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        panic,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    static REPLACED_HOOK_CALLED: AtomicBool = AtomicBool::new(false);
+
+    /// Replaces the panic hook like Bevy's `PanicHandlerPlugin` does on the web.
+    struct ReplacesHook;
+
+    impl bevy_app::Plugin for ReplacesHook {
+        fn build(&self, _app: &mut App) {
+            panic::set_hook(Box::new(|_| {
+                REPLACED_HOOK_CALLED.store(true, Ordering::Relaxed);
+            }));
+        }
+    }
+
+    #[test]
+    fn panic_hook_is_kept_after_default_plugins() {
+        // `build` calls the page, so only `finish` is run, after every plugin is built.
+        let mut app = App::new();
+        app.add_plugins(ReplacesHook);
+        bevy_app::Plugin::finish(&Plugin::default(), &mut app);
+
+        assert!(!crate::panic::panicked());
+        let _ = panic::catch_unwind(|| panic!("the user's code panicked"));
+        assert!(crate::panic::panicked());
+        assert!(REPLACED_HOOK_CALLED.load(Ordering::Relaxed));
     }
 }
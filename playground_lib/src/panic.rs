@@ -0,0 +1,283 @@
+use crate::log::error;
+use bevy_log::tracing::{
+    Subscriber,
+    field::{Field, Visit},
+    span,
+};
+use bevy_log::tracing_subscriber::{Layer, layer::Context, registry::LookupSpan};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    fmt,
+    panic::PanicHookInfo,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use wasm_bindgen::prelude::*;
+
+/// The path of the user's code, relative to the game crate.
+const USER_FILE: &str = "src/main.rs";
+
+/// Symbols belonging to the panic machinery rather than the code that panicked.
+const IGNORED_FRAMES: &[&str] = &[
+    "std::panicking",
+    "std::panic",
+    "core::panicking",
+    "std::sys",
+    "std::rt",
+    "rust_begin_unwind",
+    "__rustc",
+    "playground_lib::panic",
+];
+
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// The names of the systems currently running, innermost last.
+    static RUNNING_SYSTEMS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// Called with the JSON encoded [`PanicReport`], if the page has defined it.
+    #[wasm_bindgen(js_namespace = learnbevy, js_name = onPanic, catch)]
+    fn on_panic(report: &str) -> Result<(), JsValue>;
+
+    type Error;
+    #[wasm_bindgen(constructor)]
+    fn new() -> Error;
+    #[wasm_bindgen(structural, method, getter)]
+    fn stack(error: &Error) -> String;
+}
+
+#[cfg(test)]
+pub fn panicked() -> bool {
+    PANICKED.load(Ordering::Relaxed)
+}
+
+/// Set the panic hook, which calls the previous hook after reporting the panic.
+pub fn init() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        hook(info);
+        previous(info);
+    }));
+}
+
+/// Reports the first panic to the page and asks the app to exit.
+///
+/// Later panics are ignored, they are usually caused by the event
+/// loop calling back into the app after the first one.
+fn hook(info: &PanicHookInfo) {
+    if PANICKED.swap(true, Ordering::Relaxed) {
+        return;
+    }
+    crate::exit::exit();
+    // The page can only be called from wasm.
+    if !cfg!(target_arch = "wasm32") {
+        return;
+    }
+
+    let message = if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    };
+
+    let location = info.location().map(|location| Location {
+        file: remap_path(location.file()),
+        line: location.line(),
+        column: location.column(),
+        user_code: location.file() == USER_FILE,
+    });
+
+    let report = PanicReport {
+        system: RUNNING_SYSTEMS.with_borrow(|systems| systems.last().cloned()),
+        backtrace: backtrace(),
+        message,
+        location,
+    };
+
+    let reported = serde_json::to_string(&report)
+        .ok()
+        .is_some_and(|json| on_panic(&json).is_ok());
+    if !reported {
+        error(&format!("panicked: {}", report.message));
+    }
+}
+
+#[derive(Serialize)]
+struct PanicReport {
+    message: String,
+    location: Option<Location>,
+    /// The Bevy system that was running when the panic occurred,
+    /// if [`Plugin::system_spans`](crate::Plugin::system_spans) is set.
+    system: Option<String>,
+    /// The demangled function names of the stack, innermost first.
+    ///
+    /// This is only available when the wasm module has a name section.
+    backtrace: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct Location {
+    file: String,
+    line: u32,
+    column: u32,
+    /// If the location is within the user's code.
+    user_code: bool,
+}
+
+/// Shortens paths to dependencies and the standard library so they are
+/// meaningful to the user, e.g. `bevy_ecs-0.16.1/src/world/mod.rs`.
+fn remap_path(file: &str) -> String {
+    if let Some((_, path)) = file.split_once("/registry/src/") {
+        // Skip the registry index directory.
+        return path.split_once('/').map_or(path, |(_, path)| path).into();
+    }
+    if let Some((_, path)) = file.split_once("/rustc/") {
+        // Skip the commit hash.
+        return path.split_once('/').map_or(path, |(_, path)| path).into();
+    }
+    file.trim_start_matches("/playground/").into()
+}
+
+/// Captures and symbolizes the current stack using a JS error.
+fn backtrace() -> Option<Vec<String>> {
+    let stack = Error::new().stack();
+    let frames: Vec<String> = stack
+        .lines()
+        .filter(|line| line.contains("wasm-function"))
+        .filter_map(frame_symbol)
+        .map(|symbol| format!("{:#}", rustc_demangle::demangle(symbol)))
+        .filter(|symbol| {
+            !IGNORED_FRAMES
+                .iter()
+                .any(|ignored| symbol.starts_with(ignored))
+        })
+        .collect();
+    (!frames.is_empty()).then_some(frames)
+}
+
+/// Extracts the symbol from a line of a JS stack trace.
+///
+/// Chrome formats frames as `at symbol (wasm://...)` and Firefox
+/// as `symbol@wasm://...`. Modules without a name section only
+/// have `wasm-function[index]` in place of a symbol.
+fn frame_symbol(line: &str) -> Option<&str> {
+    let line = line.trim();
+    let symbol = match line.strip_prefix("at ") {
+        Some(rest) => rest.split_once(" (").map_or(rest, |(symbol, _)| symbol),
+        None => line.split_once('@').map(|(symbol, _)| symbol)?,
+    };
+    let symbol = symbol
+        .rsplit_once(".wasm.")
+        .map_or(symbol, |(_, symbol)| symbol);
+    (!symbol.is_empty() && !symbol.contains("wasm-function")).then_some(symbol)
+}
+
+/// A [`Layer`] that keeps track of which systems are running,
+/// using the spans created by `bevy_ecs`'s `trace` feature.
+pub struct SystemTracker;
+
+struct SystemName(String);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SystemTracker {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut visitor = NameVisitor(None);
+        attrs.record(&mut visitor);
+        if let Some(name) = visitor.0 {
+            span.extensions_mut().insert(SystemName(name));
+        }
+    }
+
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        if let Some(SystemName(name)) = span.extensions().get::<SystemName>() {
+            RUNNING_SYSTEMS.with_borrow_mut(|systems| systems.push(name.clone()));
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        if span.extensions().get::<SystemName>().is_some() {
+            RUNNING_SYSTEMS.with_borrow_mut(|systems| systems.pop());
+        }
+    }
+}
+
+struct NameVisitor(Option<String>);
+
+impl Visit for NameVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "name" {
+            self.0 = Some(value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "name" {
+            self.0 = Some(format!("{value:?}").trim_matches('"').to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_symbol_from_chrome() {
+        assert_eq!(
+            frame_symbol(
+                "    at game_bg.wasm.game::setup::h1a2b3c (wasm://wasm/game_bg.wasm-0a1b2c3d:wasm-function[42]:0x5678)"
+            ),
+            Some("game::setup::h1a2b3c")
+        );
+        assert_eq!(
+            frame_symbol(
+                "    at wasm-function[42] (wasm://wasm/0a1b2c3d:wasm-function[42]:0x5678)"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn frame_symbol_from_firefox() {
+        assert_eq!(
+            frame_symbol("game::setup::h1a2b3c@wasm://wasm/0a1b2c3d:wasm-function[42]:0x5678"),
+            Some("game::setup::h1a2b3c")
+        );
+        assert_eq!(
+            frame_symbol("@wasm://wasm/0a1b2c3d:wasm-function[42]:0x5678"),
+            None
+        );
+    }
+
+    #[test]
+    fn remap_path_shortens_dependencies_and_std() {
+        assert_eq!(
+            remap_path(
+                "/root/.cargo/registry/src/index.crates.io-6f17d22bba15001f/bevy_ecs-0.16.1/src/world/mod.rs"
+            ),
+            "bevy_ecs-0.16.1/src/world/mod.rs"
+        );
+        assert_eq!(
+            remap_path(
+                "/rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/core/src/option.rs"
+            ),
+            "library/core/src/option.rs"
+        );
+    }
+
+    #[test]
+    fn remap_path_keeps_playground_paths_relative() {
+        assert_eq!(
+            remap_path("/playground/playground_lib/src/rng.rs"),
+            "playground_lib/src/rng.rs"
+        );
+        assert_eq!(remap_path(USER_FILE), USER_FILE);
+    }
+}
@@ -0,0 +1,163 @@
+use bevy_ecs::{
+    component::Tick,
+    resource::Resource,
+    system::{ReadOnlySystemParam, SystemMeta, SystemParam},
+    world::{World, unsafe_world_cell::UnsafeWorldCell},
+};
+use rand_chacha::ChaCha8Rng;
+use rand_core::{OsRng, SeedableRng, TryRngCore};
+use std::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicU64, Ordering},
+};
+use wasm_bindgen::prelude::*;
+
+/// The seed of the running app.
+static SEED: AtomicU64 = AtomicU64::new(0);
+
+#[wasm_bindgen]
+extern "C" {
+    /// Provided by the page to choose the seed, as a `BigInt`.
+    #[wasm_bindgen(js_namespace = learnbevy, js_name = seed, catch)]
+    fn host_seed() -> Result<u64, JsValue>;
+}
+
+/// Get the seed used by the running app, so the run can be reproduced.
+#[wasm_bindgen]
+pub fn seed() -> u64 {
+    SEED.load(Ordering::Relaxed)
+}
+
+/// Pick the seed for the app.
+///
+/// The seed given by the server takes priority, followed by the one given by the page.
+/// If neither exist a random seed is used.
+pub fn resolve_seed(seed: Option<u64>) -> u64 {
+    let seed = seed
+        .or_else(|| host_seed().ok())
+        .unwrap_or_else(|| OsRng.try_next_u64().unwrap_or_default());
+    SEED.store(seed, Ordering::Relaxed);
+    seed
+}
+
+/// A random number generator seeded by the playground.
+///
+/// Using this instead of `rand::rng()` makes a run reproducible from its seed.
+/// Systems that use randomness should prefer [`SystemRng`] so that adding or
+/// reordering systems doesn't change the numbers each system receives.
+#[derive(Resource)]
+pub struct PlaygroundRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl PlaygroundRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// The seed this generator was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Create an independent generator from the same seed.
+    ///
+    /// Each stream gives a different sequence of numbers, and the
+    /// same stream will always give the same sequence for a seed.
+    pub fn fork(&self, stream: u64) -> ChaCha8Rng {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(stream);
+        rng
+    }
+}
+
+impl Deref for PlaygroundRng {
+    type Target = ChaCha8Rng;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rng
+    }
+}
+
+impl DerefMut for PlaygroundRng {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.rng
+    }
+}
+
+/// A random number generator owned by a single system.
+///
+/// It is forked from the [`PlaygroundRng`] using the system's name, so it doesn't
+/// depend on the order systems run in and doesn't conflict with other systems.
+pub struct SystemRng<'s>(&'s mut ChaCha8Rng);
+
+impl Deref for SystemRng<'_> {
+    type Target = ChaCha8Rng;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl DerefMut for SystemRng<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+// SAFETY: No world data is accessed.
+unsafe impl SystemParam for SystemRng<'_> {
+    type State = ChaCha8Rng;
+    type Item<'w, 's> = SystemRng<'s>;
+
+    fn init_state(world: &mut World, system_meta: &mut SystemMeta) -> Self::State {
+        let stream = stream_id(system_meta.name());
+        match world.get_resource::<PlaygroundRng>() {
+            Some(rng) => rng.fork(stream),
+            None => PlaygroundRng::new(seed()).fork(stream),
+        }
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        _system_meta: &SystemMeta,
+        _world: UnsafeWorldCell<'w>,
+        _change_tick: Tick,
+    ) -> Self::Item<'w, 's> {
+        SystemRng(state)
+    }
+}
+
+// SAFETY: No world data is accessed.
+unsafe impl ReadOnlySystemParam for SystemRng<'_> {}
+
+/// Hash a system's name with FNV-1a, which unlike the std hasher is stable across Rust versions.
+fn stream_id(name: &str) -> u64 {
+    name.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stream_id_is_fnv_1a() {
+        assert_eq!(stream_id(""), 0xcbf29ce484222325);
+        assert_eq!(stream_id("a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(stream_id("foobar"), 0x85944171f73967e8);
+    }
+
+    #[test]
+    fn stream_id_differs_between_systems() {
+        assert_ne!(
+            stream_id("game::spawn_enemies"),
+            stream_id("game::spawn_coins")
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use bevy_app::{App, First, MainScheduleOrder, Update};
+use bevy_ecs::{
+    resource::Resource,
+    schedule::{IntoScheduleConfigs, ScheduleLabel},
+    system::ResMut,
+    world::World,
+};
+use bevy_log::warn;
+use bevy_time::{Time, TimeSystem, Virtual};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// The number of frames to run while paused.
+static STEPS: AtomicU32 = AtomicU32::new(0);
+/// The relative speed of virtual time, stored as the bits of an `f32`.
+static SPEED: AtomicU32 = AtomicU32::new(1f32.to_bits());
+/// The fastest virtual time can pass, as `Time<Virtual>` panics on speeds that aren't finite.
+const MAX_SPEED: f32 = 100.0;
+
+#[wasm_bindgen]
+pub fn pause() {
+    PAUSED.store(true, Ordering::Relaxed);
+}
+
+#[wasm_bindgen]
+pub fn resume() {
+    STEPS.store(0, Ordering::Relaxed);
+    PAUSED.store(false, Ordering::Relaxed);
+}
+
+/// Pause the app and then run it for the given number of frames.
+#[wasm_bindgen]
+pub fn step(frames: u32) {
+    PAUSED.store(true, Ordering::Relaxed);
+    STEPS.fetch_add(frames, Ordering::Relaxed);
+}
+
+#[wasm_bindgen]
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Set how fast virtual time passes compared to real time, up to [`MAX_SPEED`].
+///
+/// Speeds that aren't finite are ignored.
+#[wasm_bindgen]
+pub fn set_speed(speed: f32) {
+    if !speed.is_finite() {
+        warn!("Ignored the time speed {speed}, it must be a finite number");
+        return;
+    }
+    SPEED.store(speed.clamp(0.0, MAX_SPEED).to_bits(), Ordering::Relaxed);
+}
+
+/// Runs [`Update`] only while the app isn't paused.
+///
+/// This takes the place of [`Update`] in the [`MainScheduleOrder`].
+#[derive(ScheduleLabel, Clone, Debug, PartialEq, Eq, Hash)]
+struct GatedUpdate;
+
+/// The state last applied to the app, so that changes the user's
+/// code makes to [`Time<Virtual>`] are only overridden by new requests.
+#[derive(Resource)]
+struct TimeControl {
+    running: bool,
+    speed: f32,
+}
+
+pub fn init(app: &mut App) {
+    let mut order = app.world_mut().resource_mut::<MainScheduleOrder>();
+    if let Some(label) = order.labels.iter_mut().find(|l| **l == Update.intern()) {
+        *label = GatedUpdate.intern();
+    }
+
+    app.insert_resource(TimeControl {
+        running: true,
+        speed: 1.0,
+    });
+    app.add_systems(First, apply_time_control.before(TimeSystem));
+    app.add_systems(GatedUpdate, run_update);
+}
+
+fn apply_time_control(mut control: ResMut<TimeControl>, time: Option<ResMut<Time<Virtual>>>) {
+    let paused = PAUSED.load(Ordering::Relaxed);
+    let stepping = paused
+        && STEPS
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |steps| {
+                steps.checked_sub(1)
+            })
+            .is_ok();
+    let running = !paused || stepping;
+    let speed = f32::from_bits(SPEED.load(Ordering::Relaxed));
+
+    let Some(mut time) = time else {
+        control.running = running;
+        return;
+    };
+    if control.running != running {
+        if running {
+            time.unpause();
+        } else {
+            time.pause();
+        }
+    }
+    if control.speed != speed {
+        time.set_relative_speed(speed);
+    }
+    *control = TimeControl { running, speed };
+}
+
+fn run_update(world: &mut World) {
+    if world.resource::<TimeControl>().running {
+        let _ = world.try_run_schedule(Update);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{log::log, panic::SystemTracker};
+use bevy_log::{
+    tracing::{
+        self, Event, Level, Metadata, Subscriber,
+        field::{Field, Visit},
+        span,
+    },
+    tracing_subscriber::{
+        EnvFilter, Layer, Registry,
+        filter::{FilterExt, filter_fn},
+        layer::Context,
+        prelude::*,
+        registry::LookupSpan,
+    },
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+/// The filter used when the server doesn't provide one.
+pub const DEFAULT_FILTER: &str = "info,wgpu=error,naga=warn";
+
+/// The number of frames that have started since the app was created.
+static FRAME: AtomicU32 = AtomicU32::new(0);
+
+pub fn advance_frame() {
+    FRAME.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn frame() -> u32 {
+    FRAME.load(Ordering::Relaxed)
+}
+
+/// Install the [`PlaygroundLayer`] and [`SystemTracker`] as the global tracing subscriber.
+///
+/// Unless `system_spans` is set neither layer is interested in the spans `bevy_ecs`
+/// gives each system, so they aren't created.
+///
+/// This must happen before `LogPlugin` is built so our subscriber wins.
+pub fn init(filter: &str, system_spans: bool) {
+    // `LogPlugin` logs an error from the root of `bevy_log` when it finds a
+    // subscriber has already been set, which would only confuse the user.
+    let filter = EnvFilter::builder()
+        .parse_lossy(filter)
+        .and(filter_fn(|metadata| {
+            !(metadata.target() == "bevy_log" && *metadata.level() == Level::ERROR)
+        }))
+        .and(filter_fn(move |metadata| {
+            system_spans || !is_system_span(metadata)
+        }));
+    // The system tracker has its own filter so panics can report the running
+    // system even when the user has filtered out `bevy_ecs`'s spans.
+    let subscriber = Registry::default()
+        .with(PlaygroundLayer.with_filter(filter))
+        .with(system_spans.then(|| SystemTracker.with_filter(filter_fn(is_system_span))));
+    // This can only fail if a subscriber was already set by a previous app.
+    let _ = tracing::subscriber::set_global_default(subscriber);
+}
+
+fn is_system_span(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span() && metadata.name() == "system"
+}
+
+/// A [`Layer`] that forwards spans and events to the page.
+///
+/// Each event is sent as a JSON [`Record`] through `console.log` with a `%t` prefix.
+pub struct PlaygroundLayer;
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for PlaygroundLayer {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(fields);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| SpanRecord {
+                        name: span.name(),
+                        fields: span
+                            .extensions()
+                            .get::<Fields>()
+                            .map(|span_fields| span_fields.values.clone())
+                            .unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let record = Record {
+            level: metadata.level().as_str(),
+            target: metadata.target(),
+            location: metadata
+                .file()
+                .zip(metadata.line())
+                .map(|(file, line)| format!("{file}:{line}")),
+            frame: frame(),
+            message: fields.message.unwrap_or_default(),
+            fields: fields.values,
+            spans,
+        };
+
+        if let Ok(json) = serde_json::to_string(&record) {
+            log(&format!("%t{json}"));
+        }
+    }
+}
+
+/// A single event as it is sent to the page.
+#[derive(Serialize)]
+struct Record<'a> {
+    level: &'a str,
+    target: &'a str,
+    location: Option<String>,
+    frame: u32,
+    message: String,
+    fields: BTreeMap<&'static str, String>,
+    /// The spans the event occurred in, from outermost to innermost.
+    spans: Vec<SpanRecord<'a>>,
+}
+
+#[derive(Serialize)]
+struct SpanRecord<'a> {
+    name: &'a str,
+    fields: BTreeMap<&'static str, String>,
+}
+
+/// The recorded fields of a span or event.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    values: BTreeMap<&'static str, String>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = Some(value.to_string());
+        } else {
+            self.values.insert(field.name(), value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = Some(format!("{value:?}"));
+        } else {
+            self.values.insert(field.name(), format!("{value:?}"));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use axum::http::{header::ACCEPT, HeaderMap};
+use serde::Serialize;
+
+/// The media type of a small framed format for sending several build artifacts
+/// in one response, clients opt in with the `Accept` header.
+///
+/// The body starts with the length of the manifest as a little endian `u32`,
+/// followed by the manifest as JSON and then the contents of each part in the
+/// order they are listed in the manifest:
+///
+/// ```text
+/// [manifest length: u32 LE][manifest JSON][part 0][part 1]...
+/// ```
+///
+/// New parts can be added without changing the version, so clients should
+/// look parts up by name and ignore the ones they don't know.
+pub const CONTENT_TYPE: &str = "application/vnd.learnbevy.artifacts.v1";
+
+/// The version of the format, included in the manifest.
+const VERSION: u32 = 1;
+
+/// A single artifact in the response.
+pub struct Part {
+    pub name: &'static str,
+    pub content_type: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    version: u32,
+    parts: Vec<PartInfo>,
+}
+
+#[derive(Serialize)]
+struct PartInfo {
+    name: &'static str,
+    content_type: &'static str,
+    length: usize,
+}
+
+/// Check if the client asked for the framed format.
+pub fn accepted(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.split(';').next().unwrap_or("").trim() == CONTENT_TYPE)
+}
+
+/// Encode the parts into the framed format.
+pub fn encode(parts: Vec<Part>) -> serde_json::Result<Vec<u8>> {
+    let manifest = Manifest {
+        version: VERSION,
+        parts: parts
+            .iter()
+            .map(|part| PartInfo {
+                name: part.name,
+                content_type: part.content_type,
+                length: part.bytes.len(),
+            })
+            .collect(),
+    };
+    let manifest = serde_json::to_vec(&manifest)?;
+
+    let parts_length: usize = parts.iter().map(|part| part.bytes.len()).sum();
+    let mut body = Vec::with_capacity(4 + manifest.len() + parts_length);
+    // The manifest is always far smaller than 4GiB.
+    body.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+    body.extend_from_slice(&manifest);
+    for mut part in parts {
+        body.append(&mut part.bytes);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_frames_the_parts_after_the_manifest() {
+        let body = encode(vec![
+            Part {
+                name: "wasm",
+                content_type: "application/wasm",
+                bytes: vec![0, 97, 115, 109],
+            },
+            Part {
+                name: "stderr",
+                content_type: "text/plain",
+                bytes: b"warning".to_vec(),
+            },
+        ])
+        .unwrap();
+
+        let manifest_length = u32::from_le_bytes(body[..4].try_into().unwrap()) as usize;
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&body[4..4 + manifest_length]).unwrap();
+        assert_eq!(
+            manifest,
+            serde_json::json!({
+                "version": 1,
+                "parts": [
+                    { "name": "wasm", "content_type": "application/wasm", "length": 4 },
+                    { "name": "stderr", "content_type": "text/plain", "length": 7 },
+                ],
+            })
+        );
+        assert_eq!(&body[4 + manifest_length..], b"\0asmwarning");
+    }
+
+    #[test]
+    fn encode_without_parts() {
+        let body = encode(Vec::new()).unwrap();
+        let manifest = br#"{"version":1,"parts":[]}"#;
+        assert_eq!(&body[..4], (manifest.len() as u32).to_le_bytes());
+        assert_eq!(&body[4..], manifest);
+    }
+}
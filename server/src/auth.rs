@@ -0,0 +1,290 @@
+use crate::{
+    config::{self, ConfigError},
+    metrics, Error,
+};
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, RawPathParams, Request},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+use tracing::Span;
+
+/// The endpoints a key can be limited to.
+const ENDPOINTS: &[&str] = &["compile", "export", "clippy", "lint", "format", "artifacts"];
+
+/// The API keys by the SHA-256 of their token.
+static KEYS: OnceLock<HashMap<String, Key>> = OnceLock::new();
+/// The requests in the current window of each caller and window length.
+///
+/// These are kept in memory, so they start again when the server restarts.
+static WINDOWS: Mutex<BTreeMap<(Caller, Duration), Window>> = Mutex::new(BTreeMap::new());
+
+const MINUTE: Duration = Duration::from_secs(60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The file with the API keys, keyed by their name.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysFile {
+    #[serde(default)]
+    keys: BTreeMap<String, Key>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Key {
+    #[serde(skip)]
+    name: String,
+    /// The SHA-256 of the token as hex, so the file doesn't contain the tokens.
+    sha256: String,
+    /// Unlimited when not set.
+    requests_per_minute: Option<u32>,
+    /// A quota over a day, to limit the total use of a key. Unlimited when not set.
+    requests_per_day: Option<u32>,
+    /// The endpoints the key can use, all of them when not set.
+    endpoints: Option<Vec<String>>,
+    /// The versions the key can use, all of them when not set.
+    versions: Option<Vec<String>>,
+}
+
+/// Who a request's limits are counted for.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Caller {
+    Key(&'static str),
+    /// Anonymous requests by the client's address, which is `None` if it isn't known.
+    Anonymous(Option<IpAddr>),
+}
+
+struct Window {
+    start: Instant,
+    requests: u32,
+}
+
+/// Load the keys in the `keys_file`, this must be called before handling requests.
+pub fn init() -> Result<(), ConfigError> {
+    let mut keys = HashMap::new();
+    if let Some(path) = &config::get().auth.keys_file {
+        let contents =
+            fs::read_to_string(path).map_err(|error| ConfigError::Read(path.clone(), error))?;
+        let file: KeysFile =
+            toml::from_str(&contents).map_err(|error| ConfigError::Parse(path.clone(), error))?;
+        for (name, mut key) in file.keys {
+            key.name = name;
+            key.validate()?;
+            let sha256 = key.sha256.to_ascii_lowercase();
+            if let Some(other) = keys.insert(sha256, key) {
+                return Err(ConfigError::Invalid(format!(
+                    "the key {} has the same token as another key",
+                    other.name
+                )));
+            }
+        }
+    }
+    KEYS.set(keys).ok();
+    Ok(())
+}
+
+impl Key {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if self.sha256.len() != 64 || !self.sha256.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return invalid(format!(
+                "the sha256 of the key {} isn't a SHA-256",
+                self.name
+            ));
+        }
+        for (field, limit) in [
+            ("requests_per_minute", self.requests_per_minute),
+            ("requests_per_day", self.requests_per_day),
+        ] {
+            if limit == Some(0) {
+                return invalid(format!(
+                    "{field} of the key {} must be at least 1",
+                    self.name
+                ));
+            }
+        }
+        for endpoint in self.endpoints.iter().flatten() {
+            if !ENDPOINTS.contains(&endpoint.as_str()) {
+                return invalid(format!(
+                    "the key {} has an unknown endpoint {endpoint:?}",
+                    self.name
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the API key of a request, and that it can make the request.
+///
+/// Requests without a key are allowed when `auth.anonymous` is set,
+/// and each client has its own `auth.anonymous_requests_per_minute`.
+pub async fn check(request: Request, next: Next) -> Result<Response, Error> {
+    let key = authenticate(request.headers())?;
+    let caller = match key {
+        Some(key) => Caller::Key(&key.name),
+        None => Caller::Anonymous(client_address(&request)),
+    };
+    let endpoint = metrics::endpoint(&request);
+    let (mut parts, body) = request.into_parts();
+    let version = RawPathParams::from_request_parts(&mut parts, &())
+        .await
+        .ok()
+        .and_then(|params| {
+            params
+                .iter()
+                .find(|(name, _)| *name == "version")
+                .map(|(_, value)| value.to_string())
+        });
+
+    let limits = match key {
+        Some(key) => {
+            Span::current().record("key", &key.name);
+            let allowed = |list: &Option<Vec<String>>, value: &str| {
+                list.as_ref()
+                    .is_none_or(|list| list.iter().any(|v| v == value))
+            };
+            if !allowed(&key.endpoints, &endpoint)
+                || version.is_some_and(|version| !allowed(&key.versions, &version))
+            {
+                return Err(Error::Forbidden);
+            }
+            [
+                (MINUTE, key.requests_per_minute),
+                (DAY, key.requests_per_day),
+            ]
+        }
+        None => [
+            (MINUTE, config::get().auth.anonymous_requests_per_minute),
+            (DAY, None),
+        ],
+    };
+    take_turn(caller, &limits)?;
+
+    Ok(next.run(Request::from_parts(parts, body)).await)
+}
+
+/// Find the key of a request from its bearer token, or `None` if it's anonymous.
+fn authenticate(headers: &HeaderMap) -> Result<Option<&'static Key>, Error> {
+    let Some(header) = headers.get(AUTHORIZATION) else {
+        return if config::get().auth.anonymous {
+            Ok(None)
+        } else {
+            Err(Error::Unauthorized)
+        };
+    };
+    let token = header
+        .to_str()
+        .ok()
+        .and_then(|header| header.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token)
+        .ok_or(Error::Unauthorized)?;
+    let sha256 = format!("{:x}", Sha256::digest(token.trim()));
+    KEYS.get()
+        .and_then(|keys| keys.get(&sha256))
+        .map(Some)
+        .ok_or(Error::Unauthorized)
+}
+
+/// The address of the client, from the `auth.client_address_header` or the connection.
+fn client_address(request: &Request) -> Option<IpAddr> {
+    match &config::get().auth.client_address_header {
+        // Proxies that append to the header put the client first.
+        Some(header) => request
+            .headers()
+            .get(header)?
+            .to_str()
+            .ok()?
+            .split(',')
+            .next()?
+            .trim()
+            .parse()
+            .ok(),
+        None => request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| address.ip()),
+    }
+}
+
+/// Count a request towards the limits of a caller, as window lengths and the requests in each.
+///
+/// Fails if any of them have been reached, in which case the request isn't counted.
+fn take_turn(caller: Caller, limits: &[(Duration, Option<u32>)]) -> Result<(), Error> {
+    let mut windows = WINDOWS.lock().unwrap();
+    // Every anonymous client gets its own windows, so they're removed once they end.
+    windows.retain(|&(_, length), window| window.start.elapsed() < length);
+    let mut time_left = None;
+    for &(length, limit) in limits {
+        let Some(limit) = limit else {
+            continue;
+        };
+        let window = windows.entry((caller, length)).or_insert_with(|| Window {
+            start: Instant::now(),
+            requests: 0,
+        });
+        if window.start.elapsed() >= length {
+            window.start = Instant::now();
+            window.requests = 0;
+        }
+        if window.requests >= limit {
+            let left = length.saturating_sub(window.start.elapsed());
+            time_left = time_left.max(Some(left));
+        }
+    }
+    if let Some(time_left) = time_left {
+        return Err(Error::RateLimit {
+            time_left: time_left.as_secs() + 1,
+        });
+    }
+
+    for &(length, limit) in limits {
+        if limit.is_some() {
+            if let Some(window) = windows.get_mut(&(caller, length)) {
+                window.requests += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn anonymous_clients_have_their_own_limits() {
+        let limits = [(MINUTE, Some(1)), (DAY, None)];
+        let first = Caller::Anonymous(Some(IpAddr::from([192, 0, 2, 1])));
+        let second = Caller::Anonymous(Some(IpAddr::from([192, 0, 2, 2])));
+        assert!(take_turn(first, &limits).is_ok());
+        assert!(matches!(
+            take_turn(first, &limits),
+            Err(Error::RateLimit { .. })
+        ));
+        assert!(take_turn(second, &limits).is_ok());
+    }
+
+    #[test]
+    fn requests_over_a_limit_are_not_counted() {
+        let caller = Caller::Key("test");
+        assert!(take_turn(caller, &[(DAY, Some(1))]).is_ok());
+        // The daily limit is reached, so the minute's limit isn't used up.
+        let limits = [(MINUTE, Some(1)), (DAY, Some(1))];
+        assert!(take_turn(caller, &limits).is_err());
+        assert!(take_turn(caller, &[(MINUTE, Some(1))]).is_ok());
+    }
+}
@@ -1,7 +1,9 @@
-use crate::{image, instances::Instance, BevyVersion, Error, RustChannel};
-use axum::{extract::Path, Json};
+use crate::{instances::Instance, registry::Toolchain, Error};
+use axum::Json;
 use serde::{Deserialize, Serialize};
-use std::time::Instant;
+use sha2::{Digest, Sha256};
+use similar::TextDiff;
+use std::{collections::BTreeSet, time::Instant};
 use tracing::{error, info, instrument};
 
 const COMMAND: &[&str] = &[
@@ -9,33 +11,179 @@ const COMMAND: &[&str] = &[
     "clippy",
     "--target",
     "wasm32-unknown-unknown",
-    "--fix",
-    "--allow-no-vcs",
+    "--message-format=json",
 ];
+/// The file the spans of the code are in.
+const FILE: &str = "src/main.rs";
+/// The most lints that can be configured in a request.
+const MAX_LINTS: usize = 100;
 
 #[derive(Deserialize)]
 pub struct ClippyRequest {
     code: String,
+    /// Apply every machine applicable suggestion.
+    #[serde(default)]
     fix: bool,
+    /// The lint groups to warn on, as well as the default ones.
+    #[serde(default)]
+    groups: Vec<LintGroup>,
+    /// Lints to warn on, such as those in `clippy::restriction`.
+    #[serde(default)]
+    warn: Vec<String>,
+    #[serde(default)]
+    allow: Vec<String>,
+    #[serde(default)]
+    deny: Vec<String>,
+    /// Only apply the suggestions with these IDs, from the `suggestions` of a previous response.
+    apply: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum LintGroup {
+    Pedantic,
+    Nursery,
+    Cargo,
+}
+
+impl LintGroup {
+    fn lint(self) -> &'static str {
+        match self {
+            LintGroup::Pedantic => "clippy::pedantic",
+            LintGroup::Nursery => "clippy::nursery",
+            LintGroup::Cargo => "clippy::cargo",
+        }
+    }
 }
 
 #[derive(Serialize)]
 pub struct ClippyResponse {
+    /// The code with the suggestions applied, when any were asked for.
     fixed_code: Option<String>,
+    /// A unified diff from the code to the `fixed_code`.
+    diff: Option<String>,
+    /// The IDs of the suggestions that were applied, which leaves
+    /// out those that overlap a suggestion applied before them.
+    applied: Vec<String>,
+    suggestions: Vec<Suggestion>,
+    /// The rendered diagnostics, followed by the output of cargo.
     stderr: String,
 }
 
+/// A change clippy suggests, which can be applied by its ID.
+#[derive(Serialize)]
+struct Suggestion {
+    /// Stays the same as long as the lint, the text it replaces and the replacement do.
+    /// Identical changes are told apart by how many come before them.
+    id: String,
+    lint: Option<String>,
+    message: String,
+    /// Where the first change is, starting at 1.
+    line: usize,
+    column: usize,
+    /// `MachineApplicable` or `MaybeIncorrect`.
+    applicability: String,
+    #[serde(skip)]
+    edits: Vec<Edit>,
+}
+
+#[derive(PartialEq)]
+struct Edit {
+    start: usize,
+    end: usize,
+    replacement: String,
+}
+
+/// The messages from `cargo --message-format=json` that are used.
+#[derive(Deserialize)]
+#[serde(tag = "reason")]
+enum CargoMessage {
+    #[serde(rename = "compiler-message")]
+    CompilerMessage { message: Diagnostic },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    message: String,
+    code: Option<DiagnosticCode>,
+    spans: Vec<DiagnosticSpan>,
+    children: Vec<Diagnostic>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticSpan {
+    file_name: String,
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    column_start: usize,
+    suggested_replacement: Option<String>,
+    suggestion_applicability: Option<String>,
+}
+
+impl ClippyRequest {
+    /// The lint flags for clippy, where later ones override earlier ones.
+    fn lint_args(&self) -> Result<Vec<String>, Error> {
+        let lints = self.warn.len() + self.allow.len() + self.deny.len();
+        if self.groups.len() + lints > MAX_LINTS {
+            return Err(Error::bad_request(format!(
+                "At most {MAX_LINTS} lints can be configured"
+            )));
+        }
+        let mut args = Vec::new();
+        for group in &self.groups {
+            args.extend([String::from("-W"), group.lint().to_string()]);
+        }
+        for (flag, lints) in [("-W", &self.warn), ("-A", &self.allow), ("-D", &self.deny)] {
+            for lint in lints {
+                if !is_lint_name(lint) {
+                    return Err(Error::bad_request(format!("{lint:?} isn't a lint name")));
+                }
+                args.extend([flag.to_string(), lint.clone()]);
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Whether it's a lint like `unused_variables` or `clippy::unwrap_used`.
+fn is_lint_name(lint: &str) -> bool {
+    let is_name = |name: &str| {
+        !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+    };
+    match lint.split_once("::") {
+        Some((tool, name)) => is_name(tool) && is_name(name),
+        None => is_name(lint),
+    }
+}
+
 #[instrument(skip(payload))]
 pub async fn clippy(
-    Path((version, channel)): Path<(BevyVersion, RustChannel)>,
+    toolchain: Toolchain,
     Json(payload): Json<ClippyRequest>,
 ) -> Result<Json<ClippyResponse>, Error> {
     info!("Started");
     let start = Instant::now();
 
-    let commands = if payload.fix { COMMAND } else { &COMMAND[0..4] };
+    let lint_args = payload.lint_args()?;
+    let mut commands = COMMAND.to_vec();
+    if !lint_args.is_empty() {
+        commands.push("--");
+        commands.extend(lint_args.iter().map(String::as_str));
+    }
 
-    let instance = Instance::new(image(version, channel), commands, &payload.code).await?;
+    let instance = Instance::new(toolchain.image(), &commands, &payload.code).await?;
 
     let output = instance.execute().await?;
 
@@ -46,15 +194,289 @@ pub async fn clippy(
         return Err(Error::Internal);
     }
 
-    let fixed_code = if payload.fix {
-        Some(instance.read_to_string("main.rs").await?)
+    let stdout = String::from_utf8(output.stdout).map_err(Error::internal)?;
+    let mut stderr = String::new();
+    let mut suggestions = Vec::new();
+    for line in stdout.lines() {
+        let Ok(CargoMessage::CompilerMessage { message }) = serde_json::from_str(line) else {
+            continue;
+        };
+        stderr.push_str(message.rendered.as_deref().unwrap_or_default());
+        collect_suggestions(&payload.code, &message, &mut suggestions);
+    }
+    stderr.push_str(&String::from_utf8(output.stderr).map_err(Error::internal)?);
+
+    let ids: BTreeSet<&str> = suggestions.iter().map(|s| s.id.as_str()).collect();
+
+    let selected: Vec<&Suggestion> = match &payload.apply {
+        Some(apply) => {
+            if let Some(id) = apply.iter().find(|id| !ids.contains(id.as_str())) {
+                return Err(Error::bad_request(format!(
+                    "There's no suggestion {id}, the code may have changed since it was linted"
+                )));
+            }
+            suggestions
+                .iter()
+                .filter(|suggestion| apply.contains(&suggestion.id))
+                .collect()
+        }
+        None if payload.fix => suggestions
+            .iter()
+            .filter(|suggestion| suggestion.applicability == "MachineApplicable")
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let (fixed_code, diff, applied) = if payload.fix || payload.apply.is_some() {
+        let (fixed_code, applied) = apply(&payload.code, &selected);
+        let diff = TextDiff::from_lines(&payload.code, &fixed_code)
+            .unified_diff()
+            .header(&format!("a/{FILE}"), &format!("b/{FILE}"))
+            .to_string();
+        (Some(fixed_code), Some(diff), applied)
     } else {
-        None
+        (None, None, Vec::new())
     };
 
     info!("Success: Completed in {:.2?}", start.elapsed());
     Ok(Json(ClippyResponse {
         fixed_code,
-        stderr: String::from_utf8(output.stderr).map_err(Error::internal)?,
+        diff,
+        applied,
+        suggestions,
+        stderr,
     }))
 }
+
+/// Find the suggestions in a diagnostic, where each child with replacements is one suggestion.
+///
+/// `code` is the code that was linted, and suggestions that are already collected are skipped.
+fn collect_suggestions(code: &str, diagnostic: &Diagnostic, suggestions: &mut Vec<Suggestion>) {
+    let lint = diagnostic.code.as_ref().map(|code| code.code.clone());
+    for child in &diagnostic.children {
+        let spans: Vec<&DiagnosticSpan> = child
+            .spans
+            .iter()
+            .filter(|span| span.suggested_replacement.is_some())
+            .collect();
+        let Some(first) = spans.first() else {
+            continue;
+        };
+        // Suggestions with placeholders or in other files can't be applied.
+        let applicability = first.suggestion_applicability.clone().unwrap_or_default();
+        if !matches!(
+            applicability.as_str(),
+            "MachineApplicable" | "MaybeIncorrect"
+        ) || spans.iter().any(|span| span.file_name != FILE)
+        {
+            continue;
+        }
+
+        let edits: Vec<Edit> = spans
+            .iter()
+            .map(|span| Edit {
+                start: span.byte_start,
+                end: span.byte_end,
+                replacement: span.suggested_replacement.clone().unwrap_or_default(),
+            })
+            .collect();
+        // The same suggestion can come from more than one diagnostic.
+        if suggestions
+            .iter()
+            .any(|suggestion| suggestion.edits == edits)
+        {
+            continue;
+        }
+
+        // The offsets aren't hashed so the ID doesn't change when code before it does.
+        let mut hasher = Sha256::new();
+        hasher.update(lint.as_deref().unwrap_or_default());
+        for edit in &edits {
+            hasher.update(b"\0");
+            hasher.update(code.get(edit.start..edit.end).unwrap_or_default());
+            hasher.update(b"\0");
+            hasher.update(&edit.replacement);
+        }
+        let mut id = String::new();
+        for occurrence in 0u32.. {
+            let mut hasher = hasher.clone();
+            hasher.update(occurrence.to_le_bytes());
+            id = format!("{:x}", hasher.finalize())[..16].to_string();
+            if !suggestions.iter().any(|suggestion| suggestion.id == id) {
+                break;
+            }
+        }
+
+        suggestions.push(Suggestion {
+            id,
+            lint: lint.clone(),
+            message: format!("{}: {}", diagnostic.message, child.message),
+            line: first.line_start,
+            column: first.column_start,
+            applicability,
+            edits,
+        });
+    }
+}
+
+/// Apply the suggestions in order, skipping any that overlap one that's already applied.
+fn apply(code: &str, suggestions: &[&Suggestion]) -> (String, Vec<String>) {
+    let mut edits: Vec<&Edit> = Vec::new();
+    let mut applied = Vec::new();
+    for suggestion in suggestions {
+        let fits = suggestion.edits.iter().all(|edit| {
+            edit.start <= edit.end
+                && code.is_char_boundary(edit.start)
+                && code.is_char_boundary(edit.end)
+                && edits
+                    .iter()
+                    .all(|other| edit.end <= other.start || other.end <= edit.start)
+        });
+        if fits {
+            edits.extend(&suggestion.edits);
+            applied.push(suggestion.id.clone());
+        }
+    }
+
+    edits.sort_by_key(|edit| edit.start);
+    let mut fixed_code = String::with_capacity(code.len());
+    let mut position = 0;
+    for edit in edits {
+        fixed_code.push_str(&code[position..edit.start]);
+        fixed_code.push_str(&edit.replacement);
+        position = edit.end;
+    }
+    fixed_code.push_str(&code[position..]);
+    (fixed_code, applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `len_zero` diagnostic suggesting to replace `start..end` of [`FILE`].
+    fn diagnostic(start: usize, end: usize, applicability: &str) -> Diagnostic {
+        serde_json::from_value(serde_json::json!({
+            "message": "length comparison to zero",
+            "code": { "code": "clippy::len_zero" },
+            "spans": [],
+            "children": [{
+                "message": "using `is_empty` is clearer and more explicit",
+                "code": null,
+                "spans": [{
+                    "file_name": FILE,
+                    "byte_start": start,
+                    "byte_end": end,
+                    "line_start": 1,
+                    "column_start": start + 1,
+                    "suggested_replacement": "v.is_empty()",
+                    "suggestion_applicability": applicability,
+                }],
+                "children": [],
+                "rendered": null,
+            }],
+            "rendered": null,
+        }))
+        .unwrap()
+    }
+
+    fn collect(code: &str, diagnostics: &[Diagnostic]) -> Vec<Suggestion> {
+        let mut suggestions = Vec::new();
+        for diagnostic in diagnostics {
+            collect_suggestions(code, diagnostic, &mut suggestions);
+        }
+        suggestions
+    }
+
+    #[test]
+    fn collect_suggestions_reads_the_edits() {
+        let code = "v.len() == 0";
+        let suggestions = collect(code, &[diagnostic(0, 12, "MachineApplicable")]);
+        let [suggestion] = &suggestions[..] else {
+            panic!("expected one suggestion");
+        };
+        assert_eq!(suggestion.lint.as_deref(), Some("clippy::len_zero"));
+        assert_eq!((suggestion.line, suggestion.column), (1, 1));
+        assert_eq!(suggestion.edits[0].replacement, "v.is_empty()");
+    }
+
+    #[test]
+    fn collect_suggestions_skips_placeholders_and_duplicates() {
+        let code = "v.len() == 0";
+        assert!(collect(code, &[diagnostic(0, 12, "HasPlaceholders")]).is_empty());
+        let diagnostics = [
+            diagnostic(0, 12, "MachineApplicable"),
+            diagnostic(0, 12, "MachineApplicable"),
+        ];
+        assert_eq!(collect(code, &diagnostics).len(), 1);
+    }
+
+    #[test]
+    fn suggestion_ids_survive_edits_before_them() {
+        let before = collect("v.len() == 0", &[diagnostic(0, 12, "MachineApplicable")]);
+        let after = collect(
+            "// A comment\nv.len() == 0",
+            &[diagnostic(13, 25, "MachineApplicable")],
+        );
+        assert_eq!(before[0].id, after[0].id);
+    }
+
+    #[test]
+    fn identical_suggestions_have_different_ids() {
+        let code = "v.len() == 0 && v.len() == 0";
+        let suggestions = collect(
+            code,
+            &[
+                diagnostic(0, 12, "MachineApplicable"),
+                diagnostic(16, 28, "MachineApplicable"),
+            ],
+        );
+        assert_eq!(suggestions.len(), 2);
+        assert_ne!(suggestions[0].id, suggestions[1].id);
+    }
+
+    fn suggestion(id: &str, edits: &[(usize, usize, &str)]) -> Suggestion {
+        Suggestion {
+            id: id.to_string(),
+            lint: None,
+            message: String::new(),
+            line: 1,
+            column: 1,
+            applicability: String::from("MachineApplicable"),
+            edits: edits
+                .iter()
+                .map(|&(start, end, replacement)| Edit {
+                    start,
+                    end,
+                    replacement: replacement.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn apply_edits_in_any_order() {
+        let second = suggestion("second", &[(8, 9, "b")]);
+        let first = suggestion("first", &[(4, 5, "a"), (0, 3, "let")]);
+        let (fixed_code, applied) = apply("var x = y;", &[&second, &first]);
+        assert_eq!(fixed_code, "let a = b;");
+        assert_eq!(applied, ["second", "first"]);
+    }
+
+    #[test]
+    fn apply_skips_overlapping_suggestions() {
+        let first = suggestion("first", &[(0, 5, "one")]);
+        let overlapping = suggestion("overlapping", &[(3, 7, "two")]);
+        let (fixed_code, applied) = apply("abcdefgh", &[&first, &overlapping]);
+        assert_eq!(fixed_code, "onefgh");
+        assert_eq!(applied, ["first"]);
+    }
+
+    #[test]
+    fn apply_skips_edits_inside_a_character() {
+        let inside = suggestion("inside", &[(1, 2, "x")]);
+        let (fixed_code, applied) = apply("é", &[&inside]);
+        assert_eq!(fixed_code, "é");
+        assert!(applied.is_empty());
+    }
+}
@@ -1,14 +1,24 @@
-use crate::{image, instances::Instance, BevyVersion, Error, RustChannel};
+use crate::{
+    artifacts::{self, Part},
+    config,
+    instances::Instance,
+    registry::Toolchain,
+    size,
+    store::{self, Kind},
+    Error,
+};
 use axum::{
-    extract::Path,
-    http::{header::CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue},
+    http::{
+        header::{CONTENT_TYPE, VARY},
+        HeaderMap, HeaderName, HeaderValue,
+    },
     Json,
 };
 use serde::Deserialize;
 use std::time::Instant;
 use tracing::{error, info, instrument};
 
-const COMMANDS: &[&str] = &["sh", "/playground/tools/build.sh"];
+const BUILD_SCRIPT: &str = "/playground/tools/build.sh";
 
 /// Extra code that is appended to the end of the user's code.
 const EXTRA_CODE: &str = r#"
@@ -19,53 +29,194 @@ use playground_lib::dbg;
 "#;
 
 /// Extra code that is appended to the end of the JS generated by wasm-bindgen.
+///
+/// `wasm_blob` can also be a compiled `WebAssembly.Module`, so the app can be started again.
 const EXTRA_JS: &str = r#"
 const imports = __wbg_get_imports();
 __wbg_init_memory(imports);
-const input = await wasm_blob.arrayBuffer();
+const input = wasm_blob instanceof WebAssembly.Module ? wasm_blob : await wasm_blob.arrayBuffer();
 const { instance, module } = await __wbg_load(input, imports);
 ref_obj.wasm = instance.exports;
 __wbg_finalize_init(instance, module);
 "#;
 
+/// The app built by [`warm`].
+const WARM_CODE: &str = r#"
+use bevy::prelude::*;
+
+fn main() {
+    App::new().add_plugins(DefaultPlugins).run();
+}
+"#;
+
 #[derive(Deserialize)]
 pub struct CompileRequest {
-    code: String,
+    pub code: String,
+    /// The default log filter for the app, using the same syntax as `RUST_LOG`.
+    /// When not given `playground_lib`'s default is used.
+    log_filter: Option<String>,
+    /// The seed for the app's `PlaygroundRng`, when not given the page or a random seed is used.
+    seed: Option<u64>,
+    /// Give every system a span, so panics can report the running system.
+    #[serde(default)]
+    system_spans: bool,
+    #[serde(default)]
+    profile: BuildProfile,
+    /// Run `wasm-opt` on the output at this level.
+    wasm_opt: Option<WasmOptLevel>,
+    /// Leave the wasm and JS out of framed responses, for clients that load them from the
+    /// stored artifacts instead. This is ignored when the `artifacts` endpoint is disabled.
+    #[serde(default)]
+    artifacts_only: bool,
+    /// Build the game to run outside the playground, see [`export`](crate::export).
+    #[serde(skip)]
+    pub standalone: bool,
+}
+
+/// The profile to build the user's code with.
+///
+/// Each one has a matching cargo profile in the image's manifest
+/// which is built while creating the image so its cache is warm.
+#[derive(Deserialize, Default, Debug, Clone, Copy)]
+enum BuildProfile {
+    /// Optimized for size, which makes for the fastest download.
+    #[default]
+    #[serde(rename = "release-size")]
+    ReleaseSize,
+    #[serde(rename = "release-speed")]
+    ReleaseSpeed,
+    /// Keeps debug info for backtraces and enables overflow checks and debug assertions.
+    #[serde(rename = "debug-with-assertions")]
+    DebugWithAssertions,
+}
+
+/// An optimization level for `wasm-opt`.
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum WasmOptLevel {
+    #[serde(rename = "1")]
+    O1,
+    #[serde(rename = "2")]
+    O2,
+    #[serde(rename = "3")]
+    O3,
+    #[serde(rename = "4")]
+    O4,
+    #[serde(rename = "s")]
+    Os,
+    #[serde(rename = "z")]
+    Oz,
 }
 
-#[instrument(skip(payload))]
+impl WasmOptLevel {
+    /// The level as passed to `build.sh`, which adds the `-O` prefix.
+    fn arg(self) -> &'static str {
+        match self {
+            WasmOptLevel::O1 => "1",
+            WasmOptLevel::O2 => "2",
+            WasmOptLevel::O3 => "3",
+            WasmOptLevel::O4 => "4",
+            WasmOptLevel::Os => "s",
+            WasmOptLevel::Oz => "z",
+        }
+    }
+}
+
+impl BuildProfile {
+    fn cargo_profile(self) -> &'static str {
+        match self {
+            BuildProfile::ReleaseSize => "release",
+            BuildProfile::ReleaseSpeed => "release-speed",
+            BuildProfile::DebugWithAssertions => "debug-assertions",
+        }
+    }
+}
+
+/// Build the user's code and respond with the wasm, JS and stderr.
+///
+/// Clients that accept [`artifacts::CONTENT_TYPE`] get the framed format. Otherwise
+/// the parts are concatenated with their lengths in the `wasm-content-length` and
+/// `js-content-length` headers, and the size report and stored artifacts are in
+/// the `size-report` and `artifacts` headers.
+///
+/// The wasm and the unmodified JS are also stored under their SHA-256 so they can be
+/// fetched again, see [`store::get`]. The JS is an ES module like any wasm-bindgen output,
+/// while the one in the response is modified for the playground to run it as a function.
+#[instrument(skip(request_headers, payload))]
 pub async fn compile(
-    Path((version, channel)): Path<(BevyVersion, RustChannel)>,
+    toolchain: Toolchain,
+    request_headers: HeaderMap,
     Json(payload): Json<CompileRequest>,
 ) -> Result<(HeaderMap, Vec<u8>), Error> {
     info!("Started");
     let start = Instant::now();
 
-    let modified_code = modify_input_code(payload.code);
-    let instance = Instance::new(image(version, channel), COMMANDS, &modified_code).await?;
-
-    let output = instance.execute().await?;
+    let artifacts_only = payload.artifacts_only && config::get().endpoints.artifacts;
+    let Build {
+        wasm,
+        original_wasm,
+        js,
+        stderr,
+    } = build(toolchain, payload, start).await?;
 
-    let code = output.status.code();
-    let stderr = String::from_utf8(output.stderr)
-        .unwrap_or_else(|_| String::from("Output contained invalid UTF8"));
+    // Parsing the wasm takes a while, as Bevy apps are tens of megabytes.
+    let (wasm, size_report) = tokio::task::spawn_blocking(move || {
+        let size_report = size::report(original_wasm.as_deref().unwrap_or(&wasm), &wasm);
+        (wasm, size_report)
+    })
+    .await
+    .map_err(Error::internal)?;
+    let size_report = size_report.map_err(Error::internal)?;
+    let size_report = serde_json::to_string(&size_report).map_err(Error::internal)?;
+    let artifacts = serde_json::json!({
+        "wasm": store::put(Kind::Wasm, &wasm).await?,
+        "js": store::put(Kind::Js, js.as_bytes()).await?,
+    })
+    .to_string();
+    let modified_js = modify_output_js(js);
 
-    // Exit code 101 means the compiler failed to build the code due to it
-    // being invalid Rust. This is a user error.
-    if code == Some(101) {
-        info!("User error: Completed in {:.2?}", start.elapsed());
-        return Err(Error::BadCode { stderr });
-    }
+    let mut headers = HeaderMap::new();
+    headers.insert(VARY, HeaderValue::from_static("accept"));
 
-    if !output.status.success() {
-        error!("Failed to build with exit code: {code:?}.\nStderr: {stderr}",);
-        return Err(Error::Internal);
+    if artifacts::accepted(&request_headers) {
+        let mut parts = Vec::new();
+        if !artifacts_only {
+            parts.push(Part {
+                name: "wasm",
+                content_type: "application/wasm",
+                bytes: wasm,
+            });
+            parts.push(Part {
+                name: "js",
+                content_type: "text/javascript",
+                bytes: modified_js.into_bytes(),
+            });
+        }
+        parts.extend([
+            Part {
+                name: "stderr",
+                content_type: "text/plain",
+                bytes: stderr.into_bytes(),
+            },
+            Part {
+                name: "size-report",
+                content_type: "application/json",
+                bytes: size_report.into_bytes(),
+            },
+            Part {
+                name: "artifacts",
+                content_type: "application/json",
+                bytes: artifacts.into_bytes(),
+            },
+        ]);
+        let body = artifacts::encode(parts).map_err(Error::internal)?;
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(artifacts::CONTENT_TYPE),
+        );
+        info!("Success: Completed in {:.2?}", start.elapsed());
+        return Ok((headers, body));
     }
 
-    let wasm = instance.read("game_bg.wasm").await?;
-    let js = instance.read_to_string("game.js").await?;
-    let modified_js = modify_output_js(js);
-
     let wasm_length = wasm.len();
     let js_length = modified_js.len();
 
@@ -73,7 +224,6 @@ pub async fn compile(
     body.extend_from_slice(modified_js.as_bytes());
     body.append(&mut stderr.into_bytes());
 
-    let mut headers = HeaderMap::new();
     headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/wasm"));
     headers.insert(
         HeaderName::from_static("wasm-content-length"),
@@ -83,16 +233,121 @@ pub async fn compile(
         HeaderName::from_static("js-content-length"),
         HeaderValue::from(js_length),
     );
+    headers.insert(
+        HeaderName::from_static("size-report"),
+        HeaderValue::from_str(&size_report).map_err(Error::internal)?,
+    );
+    headers.insert(
+        HeaderName::from_static("artifacts"),
+        HeaderValue::from_str(&artifacts).map_err(Error::internal)?,
+    );
 
     info!("Success: Completed in {:.2?}", start.elapsed());
     Ok((headers, body))
 }
 
+/// The output of building the user's code.
+pub struct Build {
+    pub wasm: Vec<u8>,
+    /// The wasm before running `wasm-opt`, if it was run.
+    pub original_wasm: Option<Vec<u8>>,
+    /// The unmodified JS generated by wasm-bindgen.
+    pub js: String,
+    pub stderr: String,
+}
+
+/// Build the user's code into wasm and its JS bindings.
+///
+/// `start` is when the request started, used for logging.
+pub async fn build(
+    toolchain: Toolchain,
+    payload: CompileRequest,
+    start: Instant,
+) -> Result<Build, Error> {
+    let plugin = plugin_expr(&payload);
+    let modified_code = modify_input_code(payload.code, &plugin);
+    let commands = [
+        "sh",
+        BUILD_SCRIPT,
+        payload.profile.cargo_profile(),
+        payload.wasm_opt.map_or("", WasmOptLevel::arg),
+    ];
+    let instance = Instance::new(toolchain.image(), &commands, &modified_code).await?;
+
+    let output = instance.execute().await?;
+
+    let code = output.status.code();
+    let stderr = String::from_utf8(output.stderr)
+        .unwrap_or_else(|_| String::from("Output contained invalid UTF8"));
+
+    // Exit code 101 means the compiler failed to build the code due to it
+    // being invalid Rust. This is a user error.
+    if code == Some(101) {
+        info!("User error: Completed in {:.2?}", start.elapsed());
+        return Err(Error::BadCode { stderr });
+    }
+
+    if !output.status.success() {
+        error!("Failed to build with exit code: {code:?}.\nStderr: {stderr}",);
+        return Err(Error::Internal);
+    }
+
+    let original_wasm = match payload.wasm_opt {
+        Some(_) => Some(instance.read("game_bg.original.wasm").await?),
+        None => None,
+    };
+    Ok(Build {
+        wasm: instance.read("game_bg.wasm").await?,
+        original_wasm,
+        js: instance.read_to_string("game.js").await?,
+        stderr,
+    })
+}
+
+/// Build an app with a toolchain, to check its image works and load it from disk.
+pub async fn warm(toolchain: Toolchain) -> bool {
+    let payload = CompileRequest {
+        code: String::from(WARM_CODE),
+        log_filter: None,
+        seed: None,
+        system_spans: false,
+        profile: BuildProfile::default(),
+        wasm_opt: None,
+        artifacts_only: false,
+        standalone: false,
+    };
+    match build(toolchain, payload, Instant::now()).await {
+        Ok(_) => true,
+        Err(Error::BadCode { stderr }) => {
+            error!("Failed to build the warm up app: {stderr}");
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Builds the `playground_lib::Plugin` expression for the request.
+fn plugin_expr(payload: &CompileRequest) -> String {
+    let seed = payload.seed;
+    let system_spans = payload.system_spans;
+    let standalone = payload.standalone;
+    // The debug representation of a string is a valid Rust string literal,
+    // and the same goes for an `Option<u64>`.
+    let log_filter = payload
+        .log_filter
+        .as_ref()
+        .map(|log_filter| format!("log_filter: {log_filter:?}, "))
+        .unwrap_or_default();
+    format!(
+        "playground_lib::Plugin {{ {log_filter}seed: {seed:?}, system_spans: {system_spans}, standalone: {standalone}, ..Default::default() }}"
+    )
+}
+
 /// Modifies the user's code to include the `playground_lib::Plugin` and the [`EXTRA_CODE`].
-fn modify_input_code(code: String) -> String {
+fn modify_input_code(code: String, plugin: &str) -> String {
     let mut modified_code = code.replacen(
         "App::new()",
-        "App::new().add_plugins(playground_lib::Plugin)",
+        &format!("App::new().add_plugins({plugin})"),
         1,
     );
     modified_code.push_str(EXTRA_CODE);
@@ -100,11 +355,21 @@ fn modify_input_code(code: String) -> String {
 }
 
 fn modify_output_js(mut code: String) -> String {
+    // Expose the JS bindings of exported functions to the page, as the raw
+    // wasm exports can't take or return anything other than numbers.
+    let exports: Vec<&str> = code
+        .lines()
+        .filter_map(|line| line.strip_prefix("export function "))
+        .filter_map(|line| line.split_once('(').map(|(name, _)| name))
+        .collect();
+    let bindings = format!("\nref_obj.bindings = {{ {} }};", exports.join(", "));
+
     // The space after export is very important as some function names contain "export".
     code = code.replace("export ", "").replace("import.meta.url", "");
     // Remove the last two lines that break things.
     code.truncate(code.len() - 35);
     // Add the extra JS to make things work.
+    code.push_str(&bindings);
     code.push_str(EXTRA_JS);
     code
 }
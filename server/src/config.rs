@@ -0,0 +1,759 @@
+use axum::http::{HeaderName, HeaderValue};
+use derive_more::Display;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+use toml::{Table, Value};
+
+/// The environment variable with the path of the config file.
+const PATH_VAR: &str = "LEARNBEVY_CONFIG";
+/// The config file used when [`PATH_VAR`] isn't set, if it exists.
+const DEFAULT_PATH: &str = "learnbevy.toml";
+/// The prefix of environment variables that override values in the config file.
+///
+/// Nested keys are separated by a double underscore, so `LEARNBEVY_TIMEOUTS__BUILD`
+/// sets `build` in the `[timeouts]` table. Values are parsed as TOML and fall back
+/// to a string, so `LEARNBEVY_ALLOWED_ORIGINS='["https://learnbevy.com"]'` is a list.
+const ENV_PREFIX: &str = "LEARNBEVY_";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Get the config loaded by [`init`].
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("config should be loaded at startup")
+}
+
+/// Load and validate the config, this must be called before [`get`].
+pub fn init() -> Result<&'static Config, ConfigError> {
+    let config = load()?;
+    config.validate()?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub mode: Mode,
+    pub bind_address: SocketAddr,
+    /// Serve over HTTPS instead of HTTP.
+    pub tls: Option<TlsConfig>,
+    /// The origins allowed to make requests, `*` allows any origin.
+    pub allowed_origins: Vec<String>,
+    /// The name of the image for a Bevy version and Rust channel,
+    /// where `{version}` and `{channel}` are replaced.
+    pub image_template: String,
+    /// The maximum number of containers to run at once, other requests wait for their turn.
+    pub max_concurrent_builds: usize,
+    /// The server reports it isn't ready once this many requests are waiting for a turn.
+    pub max_queued_builds: usize,
+    /// The maximum number of formatting containers to run at once, separate from the builds
+    /// so formatting doesn't wait behind them.
+    pub max_concurrent_formats: usize,
+    pub timeouts: Timeouts,
+    /// The directory each request's files are put in while building.
+    pub instances_dir: PathBuf,
+    /// The directory compiled artifacts are stored in.
+    pub artifacts_dir: PathBuf,
+    /// The assets avaliable in the playground, which should be a copy of `www/static/assets`.
+    ///
+    /// When it isn't set exports won't include any assets.
+    pub assets_dir: Option<PathBuf>,
+    pub endpoints: Endpoints,
+    pub registry: RegistryConfig,
+    pub images: ImagesConfig,
+    pub logging: LoggingConfig,
+    pub auth: AuthConfig,
+    pub coordinator: CoordinatorConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Worker,
+            bind_address: SocketAddr::from(([0, 0, 0, 0], 3000)),
+            tls: None,
+            allowed_origins: vec![String::from("*")],
+            image_template: String::from(
+                "ghcr.io/liamgallagher737/learnbevy-{version}-{channel}:main",
+            ),
+            max_concurrent_builds: 4,
+            max_queued_builds: 16,
+            max_concurrent_formats: 4,
+            timeouts: Timeouts::default(),
+            instances_dir: env::temp_dir().join("learnbevy-server-instances"),
+            artifacts_dir: env::temp_dir().join("learnbevy-server-artifacts"),
+            assets_dir: None,
+            endpoints: Endpoints::default(),
+            registry: RegistryConfig::default(),
+            images: ImagesConfig::default(),
+            logging: LoggingConfig::default(),
+            auth: AuthConfig::default(),
+            coordinator: CoordinatorConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Run the requests in containers on this machine.
+    Worker,
+    /// Send the requests to the least loaded of the `coordinator.workers`.
+    Coordinator,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// A PEM file with the certificate chain.
+    pub cert: PathBuf,
+    /// A PEM file with the private key.
+    pub key: PathBuf,
+}
+
+/// How long things can take in seconds before the request fails.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timeouts {
+    /// Running a container, which includes building the user's code.
+    pub build: u64,
+    /// Waiting for a turn to run a container.
+    pub queue: u64,
+    pub format: u64,
+    /// Finishing the requests in progress when shutting down, after which they're cancelled.
+    pub shutdown: u64,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            build: 120,
+            queue: 60,
+            format: 10,
+            shutdown: 30,
+        }
+    }
+}
+
+impl Timeouts {
+    pub fn build(&self) -> Duration {
+        Duration::from_secs(self.build)
+    }
+
+    pub fn queue(&self) -> Duration {
+        Duration::from_secs(self.queue)
+    }
+
+    pub fn format(&self) -> Duration {
+        Duration::from_secs(self.format)
+    }
+
+    pub fn shutdown(&self) -> Duration {
+        Duration::from_secs(self.shutdown)
+    }
+}
+
+/// Which endpoints are enabled.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Endpoints {
+    pub compile: bool,
+    pub export: bool,
+    pub clippy: bool,
+    pub lint: bool,
+    pub format: bool,
+    pub artifacts: bool,
+    pub metrics: bool,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            compile: true,
+            export: true,
+            clippy: true,
+            lint: true,
+            format: true,
+            artifacts: true,
+            metrics: true,
+        }
+    }
+}
+
+/// How the avaliable Bevy versions and Rust channels are found.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistryConfig {
+    pub source: RegistrySource,
+    /// The directory with a `{version}.Cargo.toml` for each version, like `images/manifests`.
+    ///
+    /// Only used with [`RegistrySource::Manifests`].
+    pub manifests_dir: Option<PathBuf>,
+    /// The channels each version is avaliable on.
+    ///
+    /// Only used with [`RegistrySource::Manifests`], as the images show which exist.
+    pub channels: Vec<String>,
+    /// The date each version will be removed, as `YYYY-MM-DD`.
+    pub deprecations: HashMap<String, String>,
+    /// The version the frontend should select by default.
+    pub default_version: Option<String>,
+    /// The channel the frontend should select by default.
+    pub default_channel: Option<String>,
+    /// How often to look for new versions in seconds.
+    pub refresh: u64,
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        Self {
+            source: RegistrySource::Docker,
+            manifests_dir: None,
+            channels: vec![String::from("stable"), String::from("nightly")],
+            deprecations: HashMap::new(),
+            default_version: None,
+            default_channel: None,
+            refresh: 10 * 60,
+        }
+    }
+}
+
+impl RegistryConfig {
+    pub fn refresh(&self) -> Duration {
+        Duration::from_secs(self.refresh)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrySource {
+    /// The local images that match the `image_template`.
+    Docker,
+    /// The manifests in the `manifests_dir`, with each of the `channels`.
+    Manifests,
+}
+
+/// The images that must exist, and what to do when they don't.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ImagesConfig {
+    /// The channels of each version that must have an image, checked at startup.
+    pub required: BTreeMap<String, Vec<String>>,
+    pub missing: MissingImages,
+    /// The directory with the `Dockerfile` to build images with, like `images`.
+    pub build_context: Option<PathBuf>,
+    /// Build an app with each image before it's listed in the registry, which checks
+    /// that it works and loads it from disk so the first request isn't slower.
+    pub warm: bool,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            required: BTreeMap::new(),
+            missing: MissingImages::Warn,
+            build_context: None,
+            warm: true,
+        }
+    }
+}
+
+/// What to do when a required image is missing at startup.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MissingImages {
+    Warn,
+    /// Exit, so the server never runs without them.
+    Fail,
+    /// Pull them using the `image_template`, which can point at a local registry.
+    Pull,
+    /// Build them from the `build_context`.
+    Build,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// The default filter, using the same syntax as `RUST_LOG` which overrides it.
+    pub filter: String,
+    /// Export traces over OTLP/HTTP to this endpoint, such as `http://localhost:4318/v1/traces`.
+    pub otlp_endpoint: Option<String>,
+    /// The name of this service in exported traces.
+    pub service_name: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            filter: String::from("info"),
+            otlp_endpoint: None,
+            service_name: String::from("learnbevy-compile-server"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// A JSON object per line, including the fields of the current spans.
+    Json,
+}
+
+/// Who can make requests to the endpoints that do work.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Allow requests without an API key.
+    pub anonymous: bool,
+    /// The limit for each anonymous client by their address, unlimited when not set.
+    pub anonymous_requests_per_minute: Option<u32>,
+    /// The header with the client's address, such as `cf-connecting-ip`, when behind a proxy.
+    /// Otherwise the address of the connection is used.
+    pub client_address_header: Option<String>,
+    /// A TOML file with the API keys, like `learnbevy.keys.example.toml`.
+    pub keys_file: Option<PathBuf>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            anonymous: true,
+            anonymous_requests_per_minute: None,
+            client_address_header: None,
+            keys_file: None,
+        }
+    }
+}
+
+/// The workers requests are sent to in [`Mode::Coordinator`].
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoordinatorConfig {
+    /// The URL of each worker, such as `http://10.0.0.2:3000`.
+    pub workers: Vec<String>,
+    /// How often to check the health and load of the workers in seconds.
+    pub poll: u64,
+    /// The API key to send to the workers, if they need one.
+    pub token: Option<String>,
+}
+
+impl Default for CoordinatorConfig {
+    fn default() -> Self {
+        Self {
+            workers: Vec::new(),
+            poll: 5,
+            token: None,
+        }
+    }
+}
+
+impl CoordinatorConfig {
+    pub fn poll(&self) -> Duration {
+        Duration::from_secs(self.poll)
+    }
+}
+
+#[derive(Display, Debug)]
+pub enum ConfigError {
+    #[display("Failed to read {}: {_1}", _0.display())]
+    Read(PathBuf, io::Error),
+    #[display("Failed to parse {}: {_1}", _0.display())]
+    Parse(PathBuf, toml::de::Error),
+    #[display("Invalid value for {_0}: {_1}")]
+    Env(String, String),
+    #[display("Invalid config: {_0}")]
+    Invalid(String),
+}
+
+fn load() -> Result<Config, ConfigError> {
+    let path = env::var_os(PATH_VAR).map(PathBuf::from);
+    let mut table = match &path {
+        Some(path) => read(path)?,
+        None if Path::new(DEFAULT_PATH).exists() => read(Path::new(DEFAULT_PATH))?,
+        None => Table::new(),
+    };
+
+    let mut overrides = Vec::new();
+    for (name, value) in env::vars() {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if name == PATH_VAR {
+            continue;
+        }
+        let keys: Vec<String> = key.split("__").map(str::to_lowercase).collect();
+        set(&mut table, &keys, parse_env_value(&value))
+            .map_err(|error| ConfigError::Env(name.clone(), error))?;
+        overrides.push((keys, value));
+    }
+
+    deserialize(table, overrides)
+}
+
+/// Deserialize the config, where `overrides` are the keys and raw values from the environment.
+///
+/// Values such as `0.16` are parsed as numbers, so when one of them
+/// doesn't fit its field it's used as a string instead.
+fn deserialize(
+    mut table: Table,
+    mut overrides: Vec<(Vec<String>, String)>,
+) -> Result<Config, ConfigError> {
+    loop {
+        let error = match serde_path_to_error::deserialize(Value::Table(table.clone())) {
+            Ok(config) => return Ok(config),
+            Err(error) => error,
+        };
+        let path = error.path().to_string();
+        let Some(index) = overrides
+            .iter()
+            .position(|(keys, _)| keys.join(".") == path)
+        else {
+            return Err(ConfigError::Invalid(error.to_string()));
+        };
+        let (keys, value) = overrides.swap_remove(index);
+        set(&mut table, &keys, Value::String(value)).map_err(ConfigError::Invalid)?;
+    }
+}
+
+fn read(path: &Path) -> Result<Table, ConfigError> {
+    let contents =
+        fs::read_to_string(path).map_err(|error| ConfigError::Read(path.to_path_buf(), error))?;
+    contents
+        .parse()
+        .map_err(|error| ConfigError::Parse(path.to_path_buf(), error))
+}
+
+/// Parse the value of an environment variable as TOML, falling back to a string.
+fn parse_env_value(value: &str) -> Value {
+    toml::from_str::<Table>(&format!("value = {value}"))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(value.to_string()))
+}
+
+fn set(table: &mut Table, keys: &[String], value: Value) -> Result<(), String> {
+    match keys {
+        [] => Err(String::from("missing key")),
+        [key] => {
+            table.insert(key.clone(), value);
+            Ok(())
+        }
+        [key, rest @ ..] => match table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()))
+        {
+            Value::Table(inner) => set(inner, rest, value),
+            _ => Err(format!("{key} isn't a table")),
+        },
+    }
+}
+
+impl Config {
+    fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if let Some(tls) = &self.tls {
+            for path in [&tls.cert, &tls.key] {
+                if !path.is_file() {
+                    return invalid(format!("TLS file {} doesn't exist", path.display()));
+                }
+            }
+        }
+        if self.allowed_origins.is_empty() {
+            return invalid(String::from("allowed_origins is empty, use \"*\" for any"));
+        }
+        for origin in &self.allowed_origins {
+            let is_url = origin.starts_with("http://") || origin.starts_with("https://");
+            if origin != "*" && !(is_url && HeaderValue::from_str(origin).is_ok()) {
+                return invalid(format!("allowed origin {origin:?} isn't \"*\" or a URL"));
+            }
+        }
+        for placeholder in ["{version}", "{channel}"] {
+            if !self.image_template.contains(placeholder) {
+                return invalid(format!("image_template doesn't contain {placeholder}"));
+            }
+        }
+        if self.max_concurrent_builds == 0 {
+            return invalid(String::from("max_concurrent_builds must be at least 1"));
+        }
+        if self.max_queued_builds == 0 {
+            return invalid(String::from("max_queued_builds must be at least 1"));
+        }
+        if self.max_concurrent_formats == 0 {
+            return invalid(String::from("max_concurrent_formats must be at least 1"));
+        }
+        for (name, seconds) in [
+            ("build", self.timeouts.build),
+            ("queue", self.timeouts.queue),
+            ("format", self.timeouts.format),
+            ("shutdown", self.timeouts.shutdown),
+        ] {
+            if seconds == 0 {
+                return invalid(format!("timeouts.{name} must be at least 1 second"));
+            }
+        }
+        if let Some(dir) = &self.assets_dir {
+            if !dir.is_dir() {
+                return invalid(format!("assets_dir {} doesn't exist", dir.display()));
+            }
+        }
+        if self.image_parts().1.is_empty() {
+            return invalid(String::from(
+                "image_template needs something between {version} and {channel}",
+            ));
+        }
+
+        let registry = &self.registry;
+        if registry.source == RegistrySource::Manifests {
+            match &registry.manifests_dir {
+                Some(dir) if !dir.is_dir() => {
+                    return invalid(format!("manifests_dir {} doesn't exist", dir.display()));
+                }
+                None => {
+                    return invalid(String::from(
+                        "registry.manifests_dir must be set to use manifests",
+                    ))
+                }
+                _ => {}
+            }
+            if registry.channels.is_empty() {
+                return invalid(String::from("registry.channels is empty"));
+            }
+        }
+        for (version, date) in &registry.deprecations {
+            let parts: Vec<&str> = date.split('-').collect();
+            let valid = matches!(parts.as_slice(), [y, m, d]
+                if y.len() == 4 && m.len() == 2 && d.len() == 2
+                    && parts.iter().all(|p| p.bytes().all(|b| b.is_ascii_digit())));
+            if !valid {
+                return invalid(format!(
+                    "deprecation date {date:?} for {version} isn't YYYY-MM-DD"
+                ));
+            }
+        }
+        if registry.refresh == 0 {
+            return invalid(String::from("registry.refresh must be at least 1 second"));
+        }
+        let images = &self.images;
+        if images.missing == MissingImages::Build {
+            match &images.build_context {
+                Some(dir) if !dir.join("Dockerfile").is_file() => {
+                    return invalid(format!("build_context {} has no Dockerfile", dir.display()));
+                }
+                None => {
+                    return invalid(String::from(
+                        "images.build_context must be set to build missing images",
+                    ))
+                }
+                _ => {}
+            }
+        }
+        if let Some(endpoint) = &self.logging.otlp_endpoint {
+            if !(endpoint.starts_with("http://") || endpoint.starts_with("https://")) {
+                return invalid(format!("otlp_endpoint {endpoint:?} isn't a URL"));
+            }
+        }
+        let auth = &self.auth;
+        if !auth.anonymous && auth.keys_file.is_none() {
+            return invalid(String::from(
+                "auth.anonymous is false but there's no auth.keys_file, so nothing can be used",
+            ));
+        }
+        if auth.anonymous_requests_per_minute == Some(0) {
+            return invalid(String::from(
+                "auth.anonymous_requests_per_minute must be at least 1, use anonymous = false instead",
+            ));
+        }
+        if let Some(header) = &auth.client_address_header {
+            if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return invalid(format!(
+                    "auth.client_address_header {header:?} isn't a header name"
+                ));
+            }
+        }
+        let coordinator = &self.coordinator;
+        if self.mode == Mode::Coordinator {
+            if coordinator.workers.is_empty() {
+                return invalid(String::from("coordinator.workers is empty"));
+            }
+            for worker in &coordinator.workers {
+                if !(worker.starts_with("http://") || worker.starts_with("https://")) {
+                    return invalid(format!("worker {worker:?} isn't a URL"));
+                }
+            }
+        }
+        if coordinator.poll == 0 {
+            return invalid(String::from("coordinator.poll must be at least 1 second"));
+        }
+        Ok(())
+    }
+
+    /// The name of the image for a Bevy version and Rust channel.
+    pub fn image(&self, version: &str, channel: &str) -> String {
+        self.image_template
+            .replace("{version}", version)
+            .replace("{channel}", channel)
+    }
+
+    /// Get the Bevy version and Rust channel from an image's name, the opposite of [`Config::image`].
+    pub fn parse_image(&self, image: &str) -> Option<(String, String)> {
+        let (start, middle, end) = self.image_parts();
+        let inner = image.strip_prefix(start)?.strip_suffix(end)?;
+        // Channels are simple names, while versions could contain the separator.
+        let version_first =
+            self.image_template.find("{version}") < self.image_template.find("{channel}");
+        let (version, channel) = if version_first {
+            inner.rsplit_once(middle)?
+        } else {
+            let (channel, version) = inner.split_once(middle)?;
+            (version, channel)
+        };
+        if version.is_empty() || channel.is_empty() {
+            return None;
+        }
+        Some((version.to_string(), channel.to_string()))
+    }
+
+    /// The text before, between and after the placeholders of the `image_template`.
+    fn image_parts(&self) -> (&str, &str, &str) {
+        let template = &self.image_template;
+        let (first, second) = if template.find("{version}") < template.find("{channel}") {
+            ("{version}", "{channel}")
+        } else {
+            ("{channel}", "{version}")
+        };
+        let (start, rest) = template.split_once(first).unwrap_or((template, ""));
+        let (middle, end) = rest.split_once(second).unwrap_or((rest, ""));
+        (start, middle, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_values_are_parsed_as_toml() {
+        assert_eq!(parse_env_value("8"), Value::Integer(8));
+        assert_eq!(parse_env_value("1.5"), Value::Float(1.5));
+        assert_eq!(parse_env_value("true"), Value::Boolean(true));
+        assert_eq!(
+            parse_env_value(r#"["0.16", "main"]"#),
+            Value::Array(vec![
+                Value::String(String::from("0.16")),
+                Value::String(String::from("main"))
+            ])
+        );
+        assert_eq!(
+            parse_env_value(r#""quoted""#),
+            Value::String(String::from("quoted"))
+        );
+    }
+
+    #[test]
+    fn env_values_fall_back_to_strings() {
+        for value in ["0.0.0.0:3000", "ghcr.io/user/{version}", "hello world", ""] {
+            assert_eq!(parse_env_value(value), Value::String(value.to_string()));
+        }
+    }
+
+    #[test]
+    fn env_values_that_should_be_strings_are_used_as_strings() {
+        let keys = vec![String::from("registry"), String::from("default_version")];
+        let mut table = Table::new();
+        set(&mut table, &keys, parse_env_value("0.16")).unwrap();
+        let config = deserialize(table, vec![(keys, String::from("0.16"))]).unwrap();
+        assert_eq!(config.registry.default_version.as_deref(), Some("0.16"));
+    }
+
+    #[test]
+    fn env_values_of_the_wrong_type_are_still_invalid() {
+        let keys = vec![String::from("max_concurrent_builds")];
+        let mut table = Table::new();
+        set(&mut table, &keys, parse_env_value("many")).unwrap();
+        let error = deserialize(table, vec![(keys, String::from("many"))]).unwrap_err();
+        assert!(error.to_string().contains("max_concurrent_builds"));
+    }
+
+    #[test]
+    fn set_creates_nested_tables() {
+        let mut table = Table::new();
+        let keys = [String::from("timeouts"), String::from("build")];
+        set(&mut table, &keys, Value::Integer(60)).unwrap();
+        set(&mut table, &[String::from("mode")], Value::from("worker")).unwrap();
+
+        assert_eq!(table["timeouts"]["build"], Value::Integer(60));
+        assert_eq!(table["mode"], Value::from("worker"));
+    }
+
+    #[test]
+    fn set_overrides_values_from_the_file() {
+        let mut table: Table = "[timeouts]\nbuild = 60\nqueue = 30".parse().unwrap();
+        let keys = [String::from("timeouts"), String::from("build")];
+        set(&mut table, &keys, Value::Integer(120)).unwrap();
+
+        assert_eq!(table["timeouts"]["build"], Value::Integer(120));
+        assert_eq!(table["timeouts"]["queue"], Value::Integer(30));
+    }
+
+    #[test]
+    fn set_fails_on_values_that_arent_tables() {
+        let mut table: Table = "mode = \"worker\"".parse().unwrap();
+        let keys = [String::from("mode"), String::from("inner")];
+        assert!(set(&mut table, &keys, Value::Integer(1)).is_err());
+        assert!(set(&mut table, &[], Value::Integer(1)).is_err());
+    }
+
+    fn with_template(image_template: &str) -> Config {
+        Config {
+            image_template: image_template.to_string(),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn parse_image_is_the_opposite_of_image() {
+        let config = Config::default();
+        for (version, channel) in [
+            ("0.16", "stable"),
+            ("main", "nightly"),
+            ("0.17-rc", "stable"),
+        ] {
+            let image = config.image(version, channel);
+            assert_eq!(
+                config.parse_image(&image),
+                Some((version.to_string(), channel.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn parse_image_with_the_channel_first() {
+        let config = with_template("learnbevy/{channel}/{version}");
+        assert_eq!(
+            config.parse_image("learnbevy/nightly/0.16"),
+            Some((String::from("0.16"), String::from("nightly")))
+        );
+    }
+
+    #[test]
+    fn parse_image_ignores_other_images() {
+        let config = Config::default();
+        for image in [
+            "ubuntu:20.04",
+            "ghcr.io/liamgallagher737/learnbevy-0.16-stable:latest",
+            "ghcr.io/liamgallagher737/learnbevy--stable:main",
+            "ghcr.io/liamgallagher737/learnbevy-0.16:main",
+        ] {
+            assert_eq!(config.parse_image(image), None, "{image}");
+        }
+    }
+}
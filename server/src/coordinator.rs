@@ -0,0 +1,301 @@
+use crate::{
+    config, metrics,
+    registry::{self, Registry, Toolchain},
+    request_id, Error,
+};
+use axum::{
+    body::{self, Bytes},
+    extract::Request,
+    http::{
+        header::{
+            ACCEPT, CONNECTION, CONTENT_LENGTH, CONTENT_TYPE, IF_NONE_MATCH, TRANSFER_ENCODING,
+        },
+        request::Parts,
+        HeaderName, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{task::JoinSet, time};
+use tracing::{debug, info, instrument, warn};
+
+/// How long a worker has to respond when it's polled.
+const POLL_TIMEOUT: Duration = Duration::from_secs(5);
+/// The largest request body that's forwarded, the same as the limit of [`axum::Json`].
+const MAX_BODY: usize = 2 * 1024 * 1024;
+/// The request headers that are sent on to the worker.
+const FORWARD_HEADERS: &[HeaderName] = &[CONTENT_TYPE, ACCEPT, IF_NONE_MATCH];
+/// The response headers that only apply to the connection with the worker.
+const SKIP_HEADERS: &[HeaderName] = &[CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING];
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(reqwest::Client::new);
+static WORKERS: LazyLock<Vec<Worker>> = LazyLock::new(|| {
+    config::get()
+        .coordinator
+        .workers
+        .iter()
+        .map(|url| Worker {
+            url: url.trim_end_matches('/').to_string(),
+            status: RwLock::new(None),
+            in_flight: AtomicUsize::new(0),
+        })
+        .collect()
+});
+
+struct Worker {
+    url: String,
+    /// The last time it was polled, or `None` if it couldn't be reached.
+    status: RwLock<Option<Status>>,
+    /// The requests that have been sent to it and haven't finished.
+    in_flight: AtomicUsize,
+}
+
+struct Status {
+    ready: bool,
+    running: usize,
+    queued: usize,
+    capacity: usize,
+    endpoints: Vec<String>,
+    registry: Registry,
+}
+
+/// The parts of the responses from `/ready` and `/capabilities` that are used.
+#[derive(Deserialize)]
+struct Readiness {
+    ready: bool,
+    running: usize,
+    queued: usize,
+    capacity: usize,
+}
+
+#[derive(Deserialize)]
+struct Capabilities {
+    endpoints: Vec<String>,
+}
+
+/// Check the workers every [`config::CoordinatorConfig::poll`], and list
+/// the versions that any of them have in the registry.
+pub async fn poll() {
+    let mut interval = time::interval(config::get().coordinator.poll());
+    loop {
+        interval.tick().await;
+        let mut polls: JoinSet<_> = WORKERS.iter().map(Worker::poll).collect();
+        while polls.join_next().await.is_some() {}
+
+        let statuses: Vec<_> = WORKERS.iter().map(|w| w.status.read().unwrap()).collect();
+        let registries = statuses.iter().filter_map(|status| status.as_ref());
+        registry::update(registry::merge(registries.map(|status| &status.registry)));
+    }
+}
+
+/// The number of workers that can take requests, and the number of workers.
+pub fn available() -> (usize, usize) {
+    let ready = WORKERS.iter().filter(|w| w.is_ready()).count();
+    (ready, WORKERS.len())
+}
+
+impl Worker {
+    async fn poll(&self) {
+        let status = tokio::try_join!(
+            self.get::<Readiness>("/ready"),
+            self.get::<Capabilities>("/capabilities"),
+            self.get::<Registry>("/versions"),
+        );
+        let status = match status {
+            Ok((readiness, capabilities, registry)) => {
+                if !self.is_ready() && readiness.ready {
+                    info!("{} is ready", self.url);
+                }
+                Some(Status {
+                    ready: readiness.ready,
+                    running: readiness.running,
+                    queued: readiness.queued,
+                    capacity: readiness.capacity,
+                    endpoints: capabilities.endpoints,
+                    registry,
+                })
+            }
+            // Only logged once, rather than every poll while it's down.
+            Err(error) if self.status.read().unwrap().is_some() => {
+                warn!("Failed to reach {}: {error}", self.url);
+                None
+            }
+            Err(error) => {
+                debug!("Failed to reach {}: {error}", self.url);
+                None
+            }
+        };
+        *self.status.write().unwrap() = status;
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> reqwest::Result<T> {
+        // `/ready` responds with 503 when it isn't ready, so the status isn't checked.
+        CLIENT
+            .get(format!("{}{path}", self.url))
+            .timeout(POLL_TIMEOUT)
+            .send()
+            .await?
+            .json()
+            .await
+    }
+
+    fn is_ready(&self) -> bool {
+        self.status
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|status| status.ready)
+    }
+
+    /// How busy it is, where 1 means every container is in use.
+    ///
+    /// The requests sent since it was last polled aren't included in
+    /// its status, so the ones still in flight are used if there's more.
+    fn load(&self, status: &Status) -> f64 {
+        let jobs = (status.running + status.queued).max(self.in_flight.load(Ordering::Relaxed));
+        jobs as f64 / status.capacity.max(1) as f64
+    }
+
+    async fn send(&self, parts: &Parts, body: Bytes) -> reqwest::Result<reqwest::Response> {
+        let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+        let timeouts = &config::get().timeouts;
+        let mut request = CLIENT
+            .request(parts.method.clone(), format!("{}{path}", self.url))
+            // The worker has its own timeouts, this is in case it stops responding.
+            .timeout(timeouts.queue() + timeouts.build() + Duration::from_secs(30))
+            .body(body);
+        for name in FORWARD_HEADERS {
+            if let Some(value) = parts.headers.get(name) {
+                request = request.header(name, value);
+            }
+        }
+        if let Some(id) = request_id::current() {
+            request = request.header(request_id::HEADER, id);
+        }
+        if let Some(token) = &config::get().coordinator.token {
+            request = request.bearer_auth(token);
+        }
+
+        let _in_flight = InFlight::new(&self.in_flight);
+        request.send().await
+    }
+}
+
+/// Counts a request as in flight until it's dropped, even if it's cancelled.
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl<'a> InFlight<'a> {
+    fn new(count: &'a AtomicUsize) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The ready workers that can handle a request, least loaded first.
+fn candidates(endpoint: &str, toolchain: Option<&Toolchain>) -> Vec<&'static Worker> {
+    let mut candidates: Vec<(f64, &Worker)> = WORKERS
+        .iter()
+        .filter_map(|worker| {
+            let status = worker.status.read().unwrap();
+            let status = status.as_ref().filter(|status| status.ready)?;
+            let capable = status.endpoints.iter().any(|e| e == endpoint)
+                && toolchain.is_none_or(|t| status.registry.contains(&t.version, &t.channel));
+            capable.then(|| (worker.load(status), worker))
+        })
+        .collect();
+    candidates.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+    candidates.into_iter().map(|(_, worker)| worker).collect()
+}
+
+/// Send a request for a version and channel to the least loaded worker that has them.
+pub async fn proxy_toolchain(toolchain: Toolchain, request: Request) -> Result<Response, Error> {
+    forward(Some(&toolchain), request).await
+}
+
+/// Send a request to the least loaded worker.
+pub async fn proxy(request: Request) -> Result<Response, Error> {
+    forward(None, request).await
+}
+
+#[instrument(skip_all)]
+async fn forward(toolchain: Option<&Toolchain>, request: Request) -> Result<Response, Error> {
+    let start = Instant::now();
+    let endpoint = metrics::endpoint(&request);
+    let (parts, body) = request.into_parts();
+    let body = body::to_bytes(body, MAX_BODY)
+        .await
+        .map_err(|_| Error::TooLarge)?;
+
+    // Workers that can't be reached are skipped, as the request can safely be sent again.
+    for worker in candidates(&endpoint, toolchain) {
+        match worker.send(&parts, body.clone()).await {
+            Ok(response) => {
+                info!("Sent to {} in {:.2?}", worker.url, start.elapsed());
+                return respond(response).await;
+            }
+            Err(error) if error.is_timeout() => {
+                warn!("{} took too long to respond", worker.url);
+                return Err(Error::Timeout);
+            }
+            Err(error) => {
+                warn!("Failed to reach {}: {error}", worker.url);
+                *worker.status.write().unwrap() = None;
+            }
+        }
+    }
+    warn!("No workers can take the request");
+    Err(Error::Unavailable)
+}
+
+/// Get an artifact from whichever worker stored it.
+///
+/// Artifacts are stored by their hash, so the first worker that has it will do.
+#[instrument(skip_all)]
+pub async fn artifact(request: Request) -> Result<Response, Error> {
+    let (parts, _) = request.into_parts();
+    for worker in WORKERS.iter().filter(|worker| worker.is_ready()) {
+        match worker.send(&parts, Bytes::new()).await {
+            Ok(response) if response.status() == StatusCode::NOT_FOUND => continue,
+            Ok(response) => return respond(response).await,
+            Err(error) => warn!("Failed to reach {}: {error}", worker.url),
+        }
+    }
+    Err(Error::NotFound)
+}
+
+async fn respond(response: reqwest::Response) -> Result<Response, Error> {
+    let status = response.status();
+    let mut headers = response.headers().clone();
+    for name in SKIP_HEADERS {
+        headers.remove(name);
+    }
+    let body = response.bytes().await.map_err(Error::internal)?;
+    let outcome = (!status.is_success())
+        .then(|| serde_json::from_slice::<ErrorKind>(&body).ok())
+        .flatten()
+        .map(|error| metrics::Outcome::of(&error.kind));
+    let mut response = (status, headers, body).into_response();
+    if let Some(outcome) = outcome {
+        response.extensions_mut().insert(outcome);
+    }
+    Ok(response)
+}
+
+/// The `kind` of a worker's error, so its outcome can be recorded.
+#[derive(Deserialize)]
+struct ErrorKind {
+    kind: String,
+}
@@ -0,0 +1,210 @@
+use crate::{
+    compile::{self, Build, CompileRequest},
+    config,
+    registry::Toolchain,
+    Error,
+};
+use axum::{
+    http::{
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+        HeaderMap, HeaderValue,
+    },
+    Json,
+};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Cursor, Write},
+    path::{self, Component, PathBuf},
+    time::Instant,
+};
+use tracing::{info, instrument, warn};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+/// The page that loads the game, Bevy adds the canvas to the body itself.
+const INDEX_HTML: &str = r#"<!DOCTYPE html>
+<html lang="en">
+    <head>
+        <meta charset="utf-8" />
+        <meta name="viewport" content="width=device-width, initial-scale=1" />
+        <title>Bevy Game</title>
+        <style>
+            body {
+                margin: 0;
+                height: 100vh;
+                display: flex;
+                align-items: center;
+                justify-content: center;
+                background-color: #000;
+            }
+            canvas {
+                outline: none;
+            }
+        </style>
+    </head>
+    <body>
+        <script type="module">
+            import init from "./game.js";
+            init().catch((error) => {
+                // Winit uses an exception to escape the event loop.
+                if (!error.message.startsWith("Using exceptions for control flow")) {
+                    throw error;
+                }
+            });
+        </script>
+    </body>
+</html>
+"#;
+
+/// Build the user's code into a zip that can be hosted on any static site.
+///
+/// The zip contains an `index.html`, the unmodified JS from wasm-bindgen, the wasm
+/// and the assets that the code references.
+#[instrument(skip(payload))]
+pub async fn export(
+    toolchain: Toolchain,
+    Json(mut payload): Json<CompileRequest>,
+) -> Result<(HeaderMap, Vec<u8>), Error> {
+    info!("Started");
+    let start = Instant::now();
+
+    // The page doesn't provide the functions the playground calls.
+    payload.standalone = true;
+    let references = asset_references(&payload.code);
+    let Build { wasm, js, .. } = compile::build(toolchain, payload, start).await?;
+
+    let body = tokio::task::spawn_blocking(move || bundle(&wasm, &js, &references))
+        .await
+        .map_err(Error::internal)??;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+    headers.insert(
+        CONTENT_DISPOSITION,
+        HeaderValue::from_static("attachment; filename=\"game.zip\""),
+    );
+
+    info!("Success: Completed in {:.2?}", start.elapsed());
+    Ok((headers, body))
+}
+
+fn bundle(wasm: &[u8], js: &str, references: &[PathBuf]) -> Result<Vec<u8>, Error> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default();
+
+    let mut add = |name: &str, contents: &[u8]| -> Result<(), Error> {
+        zip.start_file(name, options).map_err(Error::internal)?;
+        zip.write_all(contents)?;
+        Ok(())
+    };
+    add("index.html", INDEX_HTML.as_bytes())?;
+    add("game.js", js.as_bytes())?;
+    add("game_bg.wasm", wasm)?;
+
+    match &config::get().assets_dir {
+        Some(dir) => {
+            for (name, path) in collect_assets(dir, references)? {
+                add(&format!("assets/{name}"), &fs::read(path)?)?;
+            }
+        }
+        None if !references.is_empty() => {
+            warn!("assets_dir isn't set, the export won't include any assets");
+        }
+        None => {}
+    }
+
+    Ok(zip.finish().map_err(Error::internal)?.into_inner())
+}
+
+/// Find the string literals in the code that could be paths to assets.
+///
+/// Labels such as `#Scene0` are removed, and paths that could
+/// escape the assets directory are ignored.
+fn asset_references(code: &str) -> Vec<PathBuf> {
+    code.split('"')
+        .skip(1)
+        .step_by(2)
+        .filter_map(|literal| {
+            let path = literal.split('#').next()?;
+            if !path.contains('.') || path.contains("://") || path.contains(char::is_whitespace) {
+                return None;
+            }
+            let path = PathBuf::from(path);
+            path.components()
+                .all(|c| matches!(c, Component::Normal(_)))
+                .then_some(path)
+        })
+        .collect()
+}
+
+/// Get the files in `dir` for each of the references, keyed by their path in the zip.
+///
+/// glTF files load their buffers and textures by relative paths, so
+/// every file next to them is included as well.
+fn collect_assets(
+    dir: &path::Path,
+    references: &[PathBuf],
+) -> io::Result<BTreeMap<String, PathBuf>> {
+    let mut assets = BTreeMap::new();
+    for reference in references {
+        let path = dir.join(reference);
+        if !path.is_file() {
+            continue;
+        }
+        if path.extension().is_some_and(|e| e == "gltf") {
+            if let Some(parent) = path.parent() {
+                for entry in fs::read_dir(parent)? {
+                    let entry = entry?;
+                    if entry.file_type()?.is_file() {
+                        let sibling = reference.with_file_name(entry.file_name());
+                        assets.insert(zip_name(&sibling), entry.path());
+                    }
+                }
+            }
+        }
+        assets.insert(zip_name(reference), path);
+    }
+    Ok(assets)
+}
+
+/// Zip entries always use forward slashes.
+fn zip_name(path: &path::Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_references_finds_paths_in_string_literals() {
+        let code = r#"
+            commands.spawn(Sprite::from_image(asset_server.load("branding/icon.png")));
+            let scene = asset_server.load("models/fox.glb#Scene0");
+            info!("Loaded the fox");
+        "#;
+        assert_eq!(
+            asset_references(code),
+            [
+                PathBuf::from("branding/icon.png"),
+                PathBuf::from("models/fox.glb")
+            ]
+        );
+    }
+
+    #[test]
+    fn asset_references_ignores_paths_outside_the_assets() {
+        let code = r#"
+            load("../secret.png");
+            load("/etc/hosts.txt");
+            load("https://bevyengine.org/icon.png");
+            load("sounds/../../up.ogg");
+            load("no_extension");
+            load("two words.png");
+        "#;
+        assert!(asset_references(code).is_empty());
+    }
+}
@@ -1,14 +1,74 @@
-use crate::Error;
+use crate::{config, instances::Instance, registry, registry::Toolchain, Error};
 use axum::Json;
 use serde::{Deserialize, Serialize};
-use std::{process::Stdio, time::Instant};
+use std::{io, process::Stdio, time::Instant};
 use tokio::io::AsyncWriteExt;
 use tokio::process;
 use tracing::{error, info, instrument};
 
+/// Where the code is in the container.
+const CONTAINER_PATH: &str = "/playground/src/main.rs";
+
 #[derive(Deserialize)]
 pub struct FormatRequest {
     code: String,
+    /// The edition to parse the code with, when not given the version's edition
+    /// is used, or rustfmt's default for `/format`.
+    edition: Option<Edition>,
+    #[serde(default)]
+    config: FormatConfig,
+    /// Only format these lines, which needs a nightly rustfmt.
+    lines: Option<LineRange>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum Edition {
+    #[serde(rename = "2015")]
+    E2015,
+    #[serde(rename = "2018")]
+    E2018,
+    #[serde(rename = "2021")]
+    E2021,
+    #[serde(rename = "2024")]
+    E2024,
+}
+
+impl Edition {
+    fn arg(self) -> &'static str {
+        match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+            Edition::E2024 => "2024",
+        }
+    }
+}
+
+/// The rustfmt options that can be set, see <https://rust-lang.github.io/rustfmt>.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct FormatConfig {
+    max_width: Option<u16>,
+    hard_tabs: Option<bool>,
+    tab_spaces: Option<u8>,
+    use_small_heuristics: Option<SmallHeuristics>,
+    reorder_imports: Option<bool>,
+    use_field_init_shorthand: Option<bool>,
+    use_try_shorthand: Option<bool>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+enum SmallHeuristics {
+    Default,
+    Off,
+    Max,
+}
+
+/// The lines to format, starting at 1 and including the end.
+#[derive(Deserialize)]
+struct LineRange {
+    start: u32,
+    end: u32,
 }
 
 #[derive(Serialize)]
@@ -16,13 +76,88 @@ pub struct FormatResponse {
     formatted_code: String,
 }
 
+impl FormatConfig {
+    /// The value of rustfmt's `--config` option, or `None` if nothing is set.
+    fn arg(&self) -> Result<Option<String>, Error> {
+        if self
+            .max_width
+            .is_some_and(|width| !(1..=1000).contains(&width))
+        {
+            return Err(Error::bad_request("max_width must be between 1 and 1000"));
+        }
+        if self
+            .tab_spaces
+            .is_some_and(|spaces| !(1..=16).contains(&spaces))
+        {
+            return Err(Error::bad_request("tab_spaces must be between 1 and 16"));
+        }
+
+        let options = [
+            ("max_width", self.max_width.map(|v| v.to_string())),
+            ("hard_tabs", self.hard_tabs.map(|v| v.to_string())),
+            ("tab_spaces", self.tab_spaces.map(|v| v.to_string())),
+            (
+                "use_small_heuristics",
+                self.use_small_heuristics.map(|v| format!("{v:?}")),
+            ),
+            (
+                "reorder_imports",
+                self.reorder_imports.map(|v| v.to_string()),
+            ),
+            (
+                "use_field_init_shorthand",
+                self.use_field_init_shorthand.map(|v| v.to_string()),
+            ),
+            (
+                "use_try_shorthand",
+                self.use_try_shorthand.map(|v| v.to_string()),
+            ),
+        ];
+        let options: Vec<String> = options
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{name}={}", value?)))
+            .collect();
+        Ok((!options.is_empty()).then(|| options.join(",")))
+    }
+}
+
+impl FormatRequest {
+    /// The arguments for rustfmt, where `file` is the name it knows the code by.
+    fn args(&self, edition: Option<&str>, file: &str) -> Result<Vec<String>, Error> {
+        let mut args = Vec::new();
+        if let Some(edition) = self.edition.map(Edition::arg).or(edition) {
+            args.extend([String::from("--edition"), edition.to_string()]);
+        }
+        if let Some(config) = self.config.arg()? {
+            args.extend([String::from("--config"), config]);
+        }
+        if let Some(lines) = &self.lines {
+            if lines.start == 0 || lines.end < lines.start {
+                return Err(Error::bad_request(
+                    "lines must start at 1 and not end before they start",
+                ));
+            }
+            let file_lines =
+                serde_json::json!([{ "file": file, "range": [lines.start, lines.end] }]);
+            args.extend([
+                String::from("--unstable-features"),
+                String::from("--file-lines"),
+                file_lines.to_string(),
+            ]);
+        }
+        Ok(args)
+    }
+}
+
+/// Format the code with the host's rustfmt.
 #[instrument(skip(payload))]
 pub async fn format(Json(payload): Json<FormatRequest>) -> Result<Json<FormatResponse>, Error> {
     info!("Started");
     let start = Instant::now();
 
-    // Spawn a new rustfmt child process
+    // Spawn a new rustfmt child process, which calls the code `stdin`
     let mut command = process::Command::new("rustfmt")
+        .args(payload.args(None, "stdin")?)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -38,23 +173,67 @@ pub async fn format(Json(payload): Json<FormatRequest>) -> Result<Json<FormatRes
         .await?;
 
     // Wait for rustfmt to complete and collect the output
-    let output = command.wait_with_output().await?;
+    let output = tokio::time::timeout(config::get().timeouts.format(), command.wait_with_output())
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "rustfmt ran for too long"))??;
+
+    check(&output, start)?;
+    Ok(Json(FormatResponse {
+        formatted_code: String::from_utf8(output.stdout).map_err(Error::internal)?,
+    }))
+}
+
+/// Format the code with the rustfmt in a toolchain's image, using the version's edition.
+#[instrument(skip(payload))]
+pub async fn format_toolchain(
+    toolchain: Toolchain,
+    Json(payload): Json<FormatRequest>,
+) -> Result<Json<FormatResponse>, Error> {
+    info!("Started");
+    let start = Instant::now();
+
+    let registry = registry::get();
+    let edition = registry
+        .versions
+        .iter()
+        .find(|version| version.name == toolchain.version)
+        .and_then(|version| version.edition.as_deref());
+    let args = payload.args(edition, CONTAINER_PATH)?;
+    let mut commands = vec!["rustfmt"];
+    commands.extend(args.iter().map(String::as_str));
+    commands.push(CONTAINER_PATH);
+
+    let instance = Instance::new(toolchain.image(), &commands, &payload.code)
+        .await?
+        .with_timeout(config::get().timeouts.format())
+        .formatting();
+    let output = instance.execute().await?;
+
+    check(&output, start)?;
+    Ok(Json(FormatResponse {
+        formatted_code: instance.read_to_string("main.rs").await?,
+    }))
+}
 
-    // Respond based on result of rustfmt
+/// Check that rustfmt succeeded, where exit code 1 means the code couldn't be formatted.
+fn check(output: &std::process::Output, start: Instant) -> Result<(), Error> {
     if output.status.success() {
         info!("Success: Completed in {:.2?}", start.elapsed());
-        Ok(Json(FormatResponse {
-            formatted_code: String::from_utf8(output.stdout).map_err(Error::internal)?,
-        }))
+        Ok(())
     } else if output.status.code() == Some(1) {
         info!("Success: Completed in {:.2?}", start.elapsed());
-        Err(Error::BadCode {
-            stderr: String::from_utf8(output.stderr).map_err(Error::internal)?,
-        })
+        let stderr = String::from_utf8(output.stderr.clone()).map_err(Error::internal)?;
+        // Stable rustfmt doesn't know the option needed for `lines`.
+        if stderr.contains("Unrecognized option: 'unstable-features'") {
+            return Err(Error::bad_request(
+                "Formatting a range of lines needs a nightly rustfmt",
+            ));
+        }
+        Err(Error::BadCode { stderr })
     } else {
         error!(
             "Failed to run rustfmt: {}",
-            String::from_utf8(output.stderr).map_err(Error::internal)?
+            String::from_utf8_lossy(&output.stderr)
         );
         Err(Error::Internal)
     }
@@ -0,0 +1,181 @@
+use crate::config::{self, MissingImages};
+use std::io;
+use tokio::process;
+use tracing::{error, info, warn};
+
+/// How many lines of a failed build's output to log.
+const BUILD_LOG_LINES: usize = 20;
+
+/// An image that `images.required` says must exist.
+struct Required {
+    version: String,
+    channel: String,
+    /// The name from the `image_template`.
+    name: String,
+}
+
+fn required() -> Vec<Required> {
+    let config = config::get();
+    config
+        .images
+        .required
+        .iter()
+        .flat_map(|(version, channels)| {
+            channels.iter().map(|channel| Required {
+                version: version.clone(),
+                channel: channel.clone(),
+                name: config.image(version, channel),
+            })
+        })
+        .collect()
+}
+
+/// Check the required images exist, and deal with the missing ones as `images.missing` says.
+pub async fn prepare() -> io::Result<()> {
+    let mut missing = Vec::new();
+    for image in required() {
+        match inspect(&image.name).await? {
+            Some(id) => info!("Found {} ({id})", image.name),
+            None => missing.push(image),
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let names: Vec<&str> = missing.iter().map(|image| image.name.as_str()).collect();
+    match config::get().images.missing {
+        MissingImages::Warn => {
+            warn!("Missing images: {}", names.join(", "));
+            Ok(())
+        }
+        MissingImages::Fail => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Missing images: {}", names.join(", ")),
+        )),
+        MissingImages::Pull => {
+            for image in &missing {
+                pull(image).await?;
+            }
+            Ok(())
+        }
+        MissingImages::Build => {
+            for image in &missing {
+                build(image).await?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Run `compile_api images <check|pull|build>`, returning the exit code.
+///
+/// `pull` and `build` act on every required image, so they can also be used to update them.
+pub async fn command(args: &[String]) -> i32 {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let build_images = match args.as_slice() {
+        ["images", "check"] => return check().await,
+        ["images", "pull"] => false,
+        ["images", "build"] => true,
+        _ => {
+            error!("Usage: compile_api [images <check|pull|build>]");
+            return 2;
+        }
+    };
+    for image in required() {
+        let result = if build_images {
+            build(&image).await
+        } else {
+            pull(&image).await
+        };
+        if let Err(error) = result {
+            error!("{error}");
+            return 1;
+        }
+    }
+    0
+}
+
+/// Log the ID of each required image, failing if any are missing.
+async fn check() -> i32 {
+    let mut code = 0;
+    for image in required() {
+        match inspect(&image.name).await {
+            Ok(Some(id)) => info!("Found {} ({id})", image.name),
+            Ok(None) => {
+                error!("Missing {}", image.name);
+                code = 1;
+            }
+            Err(error) => {
+                error!("Failed to inspect {}: {error}", image.name);
+                code = 1;
+            }
+        }
+    }
+    code
+}
+
+/// Get the ID of an image, or `None` if it doesn't exist.
+async fn inspect(image: &str) -> io::Result<Option<String>> {
+    let output = process::Command::new("docker")
+        .args(["image", "inspect", "--format", "{{.Id}}", image])
+        .output()
+        .await?;
+    if output.status.success() {
+        return Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("No such image") {
+        Ok(None)
+    } else {
+        Err(io::Error::other(stderr.trim().to_string()))
+    }
+}
+
+async fn pull(image: &Required) -> io::Result<()> {
+    info!("Pulling {}", image.name);
+    let output = process::Command::new("docker")
+        .args(["pull", "--quiet", &image.name])
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "Failed to pull {}: {}",
+            image.name,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    info!("Pulled {}", image.name);
+    Ok(())
+}
+
+/// Build an image the same way as `images/build-images.sh`.
+async fn build(image: &Required) -> io::Result<()> {
+    let Some(context) = &config::get().images.build_context else {
+        return Err(io::Error::other("images.build_context isn't set"));
+    };
+    info!("Building {}, this can take a while", image.name);
+    let output = process::Command::new("docker")
+        .arg("build")
+        .args(["--build-arg", &format!("version={}", image.version)])
+        .args(["--build-arg", &format!("channel={}", image.channel)])
+        .args(["--tag", &image.name])
+        .arg(context)
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(BUILD_LOG_LINES)..].join("\n");
+        return Err(io::Error::other(format!(
+            "Failed to build {}:\n{tail}",
+            image.name
+        )));
+    }
+    info!("Built {}", image.name);
+    Ok(())
+}
@@ -1,7 +1,45 @@
 #![allow(dead_code)]
 
-use std::{env, io, path};
-use tokio::{fs, process};
+use crate::{config, metrics};
+use std::{
+    collections::BTreeMap,
+    io, path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs, process, sync::Semaphore, time};
+use tracing::{error, info, info_span, instrument, warn, Instrument};
+
+/// The label on containers with the ID of the instance that created them.
+const INSTANCE_LABEL: &str = "learnbevy.instance";
+/// The label on containers with when they were created, as seconds since the Unix epoch.
+const CREATED_LABEL: &str = "learnbevy.created";
+/// How often to look for containers and directories that were left behind.
+const REAP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Limits how many containers run at once.
+static TURNS: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(config::get().max_concurrent_builds));
+/// Limits how many formatting containers run at once, see [`Instance::formatting`].
+static FORMAT_TURNS: LazyLock<Semaphore> =
+    LazyLock::new(|| Semaphore::new(config::get().max_concurrent_formats));
+/// The number of instances waiting for a turn.
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+/// The directory of each instance that hasn't been dropped, keyed by its ID.
+static ACTIVE: Mutex<BTreeMap<u128, path::PathBuf>> = Mutex::new(BTreeMap::new());
+
+/// The number of containers running.
+pub fn running() -> usize {
+    config::get().max_concurrent_builds - TURNS.available_permits()
+}
+
+/// The number of instances waiting for a turn to run.
+pub fn queued() -> usize {
+    QUEUED.load(Ordering::Relaxed)
+}
 
 /// An instance for interfacing with the docker containers.
 pub struct Instance<'a> {
@@ -11,6 +49,10 @@ pub struct Instance<'a> {
     commands: &'a [&'a str],
     /// The code to run the commands on.
     code: &'a str,
+    /// How long the container can run for.
+    timeout: Duration,
+    /// The turns this instance waits for.
+    turns: &'static Semaphore,
 
     /// A unique ID for this instance.
     unique_id: u128,
@@ -24,29 +66,68 @@ impl<'a> Instance<'a> {
     /// This will create a local directory to be used with the container.
     pub async fn new(image: String, commands: &'a [&'a str], code: &'a str) -> io::Result<Self> {
         let unique_id = fastrand::u128(..);
-        let bind_dir = env::temp_dir()
-            .join("learnbevy-server-instances")
-            .join(unique_id.to_string());
+        let bind_dir = config::get().instances_dir.join(unique_id.to_string());
 
         fs::create_dir_all(&bind_dir).await?;
-        fs::write(bind_dir.join("main.rs"), code).await?;
-
-        Ok(Self {
+        // From here on dropping the instance removes the entry and the directory.
+        ACTIVE.lock().unwrap().insert(unique_id, bind_dir.clone());
+        let instance = Self {
             image,
             commands,
             code,
+            timeout: config::get().timeouts.build(),
+            turns: &TURNS,
             unique_id,
             bind_dir,
-        })
+        };
+        fs::write(instance.bind_dir.join("main.rs"), code).await?;
+        Ok(instance)
+    }
+
+    /// Limit how long the container can run for, instead of the build timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Take turns with the other formatting containers instead of the builds.
+    pub fn formatting(mut self) -> Self {
+        self.turns = &FORMAT_TURNS;
+        self
     }
 
     /// Execute the comtainer with the given inputs.
+    ///
+    /// This waits for a turn if too many containers are running, and
+    /// fails with [`io::ErrorKind::TimedOut`] if either takes too long.
     pub async fn execute(&self) -> io::Result<std::process::Output> {
-        process::Command::new("docker")
+        let timeouts = &config::get().timeouts;
+        let queued_at = Instant::now();
+        let queued = Queued::new();
+        let turn = time::timeout(timeouts.queue(), self.turns.acquire())
+            .instrument(info_span!("queue"))
+            .await;
+        drop(queued);
+        let _turn = turn
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "waited too long for a turn"))?
+            .map_err(io::Error::other)?;
+        metrics::observe_queue_wait(queued_at);
+
+        // The container is created and started separately to measure how long creating it takes.
+        let created_at = Instant::now();
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let create = process::Command::new("docker")
             .args([
-                "run",
+                "create",
                 "--name",
                 &self.unique_id.to_string(),
+                "--label",
+                &format!("{INSTANCE_LABEL}={}", self.unique_id),
+                "--label",
+                &format!("{CREATED_LABEL}={created}"),
                 "-v",
                 &format!("{}:/playground/src/:z", self.bind_dir.display()),
                 "--quiet",
@@ -57,23 +138,233 @@ impl<'a> Instance<'a> {
             ])
             .args(self.commands)
             .output()
-            .await
+            .instrument(info_span!("create", image = %self.image))
+            .await?;
+        if !create.status.success() {
+            let stderr = String::from_utf8_lossy(&create.stderr);
+            if stderr.contains("No such image") {
+                // The registry only lists images that exist, so it was removed since it refreshed.
+                return Err(io::Error::other(format!(
+                    "The image {} is missing, it may have been removed since the registry refreshed",
+                    self.image
+                )));
+            }
+            return Err(io::Error::other(format!(
+                "Failed to create container: {stderr}"
+            )));
+        }
+        metrics::observe_container_start(created_at);
+
+        let output = process::Command::new("docker")
+            .args(["start", "--attach", &self.unique_id.to_string()])
+            .kill_on_drop(true)
+            .output();
+
+        let output = time::timeout(self.timeout, output)
+            .instrument(info_span!("container", image = %self.image));
+        match output.await {
+            Ok(output) => output,
+            Err(_) => {
+                // Killing the docker client leaves the container running.
+                process::Command::new("docker")
+                    .args(["kill", &self.unique_id.to_string()])
+                    .output()
+                    .await?;
+                Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "the container ran for too long",
+                ))
+            }
+        }
     }
 
     /// Read a file in the instance's directory as a [`Vec<u8>`].
+    #[instrument(skip_all, fields(path = %path.as_ref().display()))]
     pub async fn read<P: AsRef<path::Path>>(&self, path: P) -> io::Result<Vec<u8>> {
         fs::read(self.bind_dir.join(path)).await
     }
 
     /// Read a file in the instance's directory as a [`String`].
+    #[instrument(skip_all, fields(path = %path.as_ref().display()))]
     pub async fn read_to_string<P: AsRef<path::Path>>(&self, path: P) -> io::Result<String> {
         fs::read_to_string(self.bind_dir.join(path)).await
     }
 }
 
+/// Counts an instance as waiting for a turn until it's dropped, even if the request is cancelled.
+struct Queued;
+
+impl Queued {
+    fn new() -> Self {
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        QUEUED.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 impl Drop for Instance<'_> {
     fn drop(&mut self) {
+        ACTIVE.lock().unwrap().remove(&self.unique_id);
         let dir = self.bind_dir.clone();
         tokio::spawn(async move { fs::remove_dir_all(dir).await });
     }
 }
+
+/// Remove the containers and directories of the instances that are still running.
+///
+/// This is for shutting down, as the tasks that own them won't get to clean up.
+pub async fn cleanup() {
+    let active = std::mem::take(&mut *ACTIVE.lock().unwrap());
+    if active.is_empty() {
+        return;
+    }
+    info!("Cleaning up {} instances", active.len());
+
+    // Removing also kills them, and handles containers that were created but never started.
+    let ids: Vec<String> = active.keys().map(u128::to_string).collect();
+    match process::Command::new("docker")
+        .args(["rm", "--force"])
+        .args(&ids)
+        .output()
+        .await
+    {
+        // Instances that hadn't created their container yet make this fail, so it's only logged.
+        Ok(output) if !output.status.success() => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            info!("Failed to remove some containers: {}", stderr.trim());
+        }
+        Ok(_) => {}
+        Err(error) => warn!("Failed to remove containers: {error}"),
+    }
+
+    for dir in active.values() {
+        if let Err(error) = fs::remove_dir_all(dir).await {
+            if error.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {error}", dir.display());
+            }
+        }
+    }
+}
+
+/// Remove the containers and directories that instances left behind every [`REAP_INTERVAL`].
+///
+/// These are from instances that were never dropped, such as when the server crashed.
+/// Anything that could still belong to a request, from this server or another
+/// sharing the same docker daemon, is left alone.
+pub async fn reap() {
+    let mut interval = time::interval(REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match reap_containers().await {
+            Ok(0) => {}
+            Ok(removed) => info!("Removed {removed} orphaned containers"),
+            Err(error) => error!("Failed to remove orphaned containers: {error}"),
+        }
+        match reap_dirs().await {
+            Ok(0) => {}
+            Ok(removed) => info!("Removed {removed} orphaned instance directories"),
+            Err(error) => error!("Failed to remove orphaned instance directories: {error}"),
+        }
+    }
+}
+
+/// How old something has to be before it's left behind, as no request can take longer.
+fn orphaned_after() -> Duration {
+    let timeouts = &config::get().timeouts;
+    timeouts.queue() + timeouts.build() + Duration::from_secs(60)
+}
+
+async fn reap_containers() -> io::Result<usize> {
+    let output = process::Command::new("docker")
+        .args([
+            "ps",
+            "--all",
+            "--filter",
+            &format!("label={INSTANCE_LABEL}"),
+        ])
+        .args([
+            "--format",
+            &format!("{{{{.Names}}}} {{{{.Label \"{CREATED_LABEL}\"}}}}"),
+        ])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr)));
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let orphaned: Vec<&str> = std::str::from_utf8(&output.stdout)
+        .map_err(io::Error::other)?
+        .lines()
+        .filter_map(|line| {
+            let (name, created) = line.split_once(' ')?;
+            let created = Duration::from_secs(created.parse().ok()?);
+            let id = name.parse::<u128>().ok()?;
+            let active = ACTIVE.lock().unwrap().contains_key(&id);
+            (!active && now.saturating_sub(created) > orphaned_after()).then_some(name)
+        })
+        .collect();
+    if orphaned.is_empty() {
+        return Ok(0);
+    }
+
+    let output = process::Command::new("docker")
+        .args(["rm", "--force"])
+        .args(&orphaned)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr)));
+    }
+    metrics::observe_reaped("container", orphaned.len());
+    Ok(orphaned.len())
+}
+
+async fn reap_dirs() -> io::Result<usize> {
+    let mut entries = match fs::read_dir(&config::get().instances_dir).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        let Some(id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| n.parse::<u128>().ok())
+        else {
+            continue;
+        };
+        if ACTIVE.lock().unwrap().contains_key(&id) {
+            continue;
+        }
+        // A directory that can't be removed is skipped so it doesn't hold up the others.
+        let path = entry.path();
+        let age = match entry
+            .metadata()
+            .await
+            .and_then(|metadata| metadata.modified())
+        {
+            Ok(modified) => modified.elapsed().unwrap_or_default(),
+            Err(error) => {
+                warn!("Failed to check the age of {}: {error}", path.display());
+                continue;
+            }
+        };
+        if age > orphaned_after() {
+            match fs::remove_dir_all(&path).await {
+                Ok(()) => removed += 1,
+                Err(error) => warn!("Failed to remove {}: {error}", path.display()),
+            }
+        }
+    }
+    metrics::observe_reaped("directory", removed);
+    Ok(removed)
+}
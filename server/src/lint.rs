@@ -1,5 +1,5 @@
-use crate::{image, instances::Instance, BevyVersion, Error, RustChannel};
-use axum::{extract::Path, Json};
+use crate::{instances::Instance, registry::Toolchain, Error};
+use axum::Json;
 use serde::{Deserialize, Serialize};
 use std::time::Instant;
 use tracing::{error, info, instrument};
@@ -18,13 +18,13 @@ pub struct LintResponse {
 
 #[instrument(skip(payload))]
 pub async fn lint(
-    Path((version, channel)): Path<(BevyVersion, RustChannel)>,
+    toolchain: Toolchain,
     Json(payload): Json<LintRequest>,
 ) -> Result<Json<LintResponse>, Error> {
     info!("Started");
     let start = Instant::now();
 
-    let instance = Instance::new(image(version, channel), COMMAND, &payload.code).await?;
+    let instance = Instance::new(toolchain.image(), COMMAND, &payload.code).await?;
 
     let output = instance.execute().await?;
 
@@ -1,87 +1,255 @@
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 
 use axum::{
-    http::{header::CONTENT_TYPE, HeaderName, Method, StatusCode},
+    http::{
+        header::{
+            ACCEPT, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_TYPE, RETRY_AFTER, WWW_AUTHENTICATE,
+        },
+        HeaderName, HeaderValue, Method, StatusCode,
+    },
+    middleware,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{get, post, MethodRouter},
     Json, Router,
 };
-use derive_more::Display;
-use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
+use config::{LoggingConfig, Mode};
+use serde::Serialize;
+use tokio::{net::TcpListener, time};
 use tower_http::{
     compression::CompressionLayer,
-    cors::{Any, CorsLayer},
+    cors::{AllowOrigin, CorsLayer},
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
+mod artifacts;
+mod auth;
 mod clippy;
 mod compile;
+mod config;
+mod coordinator;
+mod export;
 mod format;
+mod images;
 mod instances;
 mod lint;
+mod metrics;
+mod registry;
+mod request_id;
+mod shutdown;
+mod size;
+mod status;
+mod store;
+mod telemetry;
+mod tls;
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
+    let config = match config::init().and_then(|config| auth::init().map(|()| config)) {
+        Ok(config) => config,
+        Err(error) => {
+            telemetry::init(&LoggingConfig::default());
+            error!("{error}");
+            std::process::exit(1);
+        }
+    };
+    let tracer_provider = telemetry::init(&config.logging);
 
-    let app = Router::new()
-        .route("/compile/:version/:channel", post(compile::compile))
-        .route("/clippy/:version/:channel", post(clippy::clippy))
-        .route("/lint/:version/:channel", post(lint::lint))
-        .route("/format", post(format::format))
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(images::command(&args).await);
+    }
+
+    tokio::spawn(shutdown::listen());
+    let coordinator = config.mode == Mode::Coordinator;
+    if coordinator {
+        tokio::spawn(coordinator::poll());
+    } else {
+        // Pulling and building images can take minutes, so they're cancelled by a shutdown.
+        let prepared = tokio::select! {
+            prepared = images::prepare() => Some(prepared),
+            _ = shutdown::requested() => None,
+        };
+        match prepared {
+            Some(Ok(())) => {}
+            Some(Err(error)) => {
+                error!("{error}");
+                std::process::exit(1);
+            }
+            None => {
+                instances::cleanup().await;
+                info!("Stopped while preparing the images");
+                telemetry::shutdown(tracer_provider);
+                return;
+            }
+        }
+        tokio::spawn(store::cleanup());
+        tokio::spawn(instances::reap());
+        tokio::spawn(registry::refresh());
+    }
+
+    // Coordinators send the requests that do work to the workers instead.
+    let work = |handler: MethodRouter, proxy: MethodRouter| {
+        if coordinator {
+            proxy
+        } else {
+            handler
+        }
+    };
+    let endpoints = &config.endpoints;
+    let mut app = Router::new();
+    if endpoints.compile {
+        app = app.route(
+            "/compile/:version/:channel",
+            work(post(compile::compile), post(coordinator::proxy_toolchain)),
+        );
+    }
+    if endpoints.export {
+        app = app.route(
+            "/export/:version/:channel",
+            work(post(export::export), post(coordinator::proxy_toolchain)),
+        );
+    }
+    if endpoints.clippy {
+        app = app.route(
+            "/clippy/:version/:channel",
+            work(post(clippy::clippy), post(coordinator::proxy_toolchain)),
+        );
+    }
+    if endpoints.lint {
+        app = app.route(
+            "/lint/:version/:channel",
+            work(post(lint::lint), post(coordinator::proxy_toolchain)),
+        );
+    }
+    if endpoints.format {
+        app = app
+            .route(
+                "/format",
+                work(post(format::format), post(coordinator::proxy)),
+            )
+            .route(
+                "/format/:version/:channel",
+                work(
+                    post(format::format_toolchain),
+                    post(coordinator::proxy_toolchain),
+                ),
+            );
+    }
+    if endpoints.artifacts {
+        app = app.route(
+            "/artifacts/:name",
+            work(get(store::get), get(coordinator::artifact)),
+        );
+    }
+    // Only the routes above need a key and are tracked, so polling
+    // the routes below doesn't skew the metrics.
+    app = app
+        .route_layer(middleware::from_fn(auth::check))
+        .route_layer(middleware::from_fn(metrics::track));
+
+    app = app
+        .route("/health", get(status::health))
+        .route("/ready", get(status::ready))
+        .route("/capabilities", get(status::capabilities))
+        .route("/versions", get(registry::versions));
+    if endpoints.metrics {
+        app = app.route("/metrics", get(metrics::metrics));
+    }
+
+    let allow_origin = if config.allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .allowed_origins
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin).unwrap()),
+        )
+    };
+
+    let app = app
+        .layer(middleware::from_fn(request_id::layer))
         .layer(CompressionLayer::new())
         .layer(
             CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods([Method::POST])
-                .allow_headers([CONTENT_TYPE])
+                .allow_origin(allow_origin)
+                .allow_methods([Method::GET, Method::POST])
+                .allow_headers([CONTENT_TYPE, ACCEPT, AUTHORIZATION, request_id::HEADER])
                 .max_age(Duration::from_secs(60 * 60 * 24))
                 .expose_headers([
                     HeaderName::from_static("wasm-content-length"),
                     HeaderName::from_static("js-content-length"),
+                    HeaderName::from_static("size-report"),
+                    HeaderName::from_static("artifacts"),
+                    CONTENT_DISPOSITION,
+                    request_id::HEADER,
                 ]),
         );
 
-    let listener = TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    info!("Listening at http://{}", listener.local_addr().unwrap());
-    axum::serve(listener, app).await.unwrap();
-}
-
-/// The version of Bevy for a request.
-#[derive(Deserialize, Display, Debug)]
-enum BevyVersion {
-    #[serde(rename = "main")]
-    #[display("main")]
-    Main,
-    // When updating this for new Bevy versions, the number value
-    // should also be updated so saved caches are invalidated.
-    #[serde(rename = "0.16")]
-    #[display("0.16")]
-    V0_16 = 16,
-}
-
-/// The channel of Rust for a request.
-#[derive(Deserialize, Display, Debug)]
-enum RustChannel {
-    #[serde(rename = "stable")]
-    #[display("stable")]
-    Stable,
-    #[serde(rename = "nightly")]
-    #[display("nightly")]
-    Nightly,
-}
+    let listener = TcpListener::bind(config.bind_address).await.unwrap();
+    let serve = async {
+        match &config.tls {
+            Some(tls_config) => {
+                let acceptor = tls::acceptor(tls_config).unwrap();
+                info!("Listening at https://{}", listener.local_addr().unwrap());
+                tls::serve(listener, acceptor, app).await
+            }
+            None => {
+                info!("Listening at http://{}", listener.local_addr().unwrap());
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(shutdown::requested())
+                .await
+            }
+        }
+    };
+    // The requests in progress get until the deadline to finish, then they're cancelled.
+    let deadline = async {
+        shutdown::requested().await;
+        time::sleep(config.timeouts.shutdown()).await;
+    };
+    tokio::select! {
+        result = serve => result.unwrap(),
+        _ = deadline => warn!("The requests in progress took too long, cancelling them"),
+    }
 
-fn image(version: BevyVersion, channel: RustChannel) -> String {
-    format!("ghcr.io/liamgallagher737/learnbevy-{version}-{channel}:main")
+    instances::cleanup().await;
+    info!("Stopped");
+    telemetry::shutdown(tracer_provider);
 }
 
 /// The error type for all handlers.
+///
+/// The response body also includes the ID of the request so it can be found in the logs.
 #[derive(Serialize)]
 #[serde(tag = "kind")]
 enum Error {
     Internal,
-    BadCode { stderr: String },
+    BadCode {
+        stderr: String,
+    },
+    /// The request is valid JSON but asks for something that can't be done.
+    BadRequest {
+        message: String,
+    },
+    NotFound,
+    /// The request needs a valid API key.
+    Unauthorized,
+    /// The API key can't use this endpoint or version.
+    Forbidden,
+    /// The API key, or anonymous client, made too many requests this minute or day.
+    RateLimit {
+        /// Seconds until another request can be made.
+        time_left: u64,
+    },
+    /// No workers can take the request, when running as a coordinator.
+    Unavailable,
+    /// The request took too long, either waiting for its turn or running.
+    Timeout,
+    /// The request body is bigger than is allowed.
+    TooLarge,
 }
 
 impl Error {
@@ -90,6 +258,28 @@ impl Error {
         error!("Failed to handle request: {error}");
         Self::Internal
     }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::BadRequest {
+            message: message.into(),
+        }
+    }
+
+    /// The name of the variant, as in the `kind` of the response body.
+    fn kind(&self) -> &'static str {
+        match self {
+            Error::Internal => "Internal",
+            Error::BadCode { stderr: _ } => "BadCode",
+            Error::BadRequest { message: _ } => "BadRequest",
+            Error::NotFound => "NotFound",
+            Error::Unauthorized => "Unauthorized",
+            Error::Forbidden => "Forbidden",
+            Error::RateLimit { time_left: _ } => "RateLimit",
+            Error::Unavailable => "Unavailable",
+            Error::Timeout => "Timeout",
+            Error::TooLarge => "TooLarge",
+        }
+    }
 }
 
 impl IntoResponse for Error {
@@ -97,15 +287,48 @@ impl IntoResponse for Error {
         let status = match self {
             Error::Internal => StatusCode::INTERNAL_SERVER_ERROR,
             Error::BadCode { stderr: _ } => StatusCode::BAD_REQUEST,
+            Error::BadRequest { message: _ } => StatusCode::BAD_REQUEST,
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::RateLimit { time_left: _ } => StatusCode::TOO_MANY_REQUESTS,
+            Error::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Timeout => StatusCode::SERVICE_UNAVAILABLE,
+            Error::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        };
+        let header = match self {
+            Error::Unauthorized => Some((WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))),
+            Error::RateLimit { time_left } => Some((RETRY_AFTER, HeaderValue::from(time_left))),
+            _ => None,
         };
-        let mut response = Json(self).into_response();
+        let outcome = metrics::Outcome::of(self.kind());
+        let body = ErrorBody {
+            error: self,
+            request_id: request_id::current(),
+        };
+        let mut response = Json(body).into_response();
         *response.status_mut() = status;
+        response.extensions_mut().insert(outcome);
+        if let Some((name, value)) = header {
+            response.headers_mut().insert(name, value);
+        }
         response
     }
 }
 
+#[derive(Serialize)]
+struct ErrorBody {
+    #[serde(flatten)]
+    error: Error,
+    request_id: Option<String>,
+}
+
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
+        if error.kind() == std::io::ErrorKind::TimedOut {
+            info!("Timed out: {error}");
+            return Self::Timeout;
+        }
         Self::internal(error)
     }
 }
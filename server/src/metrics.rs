@@ -0,0 +1,218 @@
+use crate::{instances, registry::Toolchain};
+use axum::{
+    extract::{FromRequestParts, MatchedPath, Request},
+    http::{header::CONTENT_TYPE, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    exponential_buckets, register_histogram, register_histogram_vec, register_int_counter_vec,
+    register_int_gauge, Encoder, Histogram, HistogramVec, IntCounterVec, IntGauge, TextEncoder,
+};
+use std::{sync::LazyLock, time::Instant};
+use tracing::error;
+
+static REQUEST_DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "learnbevy_request_duration_seconds",
+        "How long requests take to handle",
+        &["endpoint", "version", "channel"],
+        vec![0.025, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0]
+    )
+    .unwrap()
+});
+
+static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "learnbevy_requests_total",
+        "The number of requests handled by their outcome",
+        &["endpoint", "version", "channel", "outcome"]
+    )
+    .unwrap()
+});
+
+static CONTAINER_START: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "learnbevy_container_start_seconds",
+        "How long it takes to create a container",
+        vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]
+    )
+    .unwrap()
+});
+
+static QUEUE_WAIT: LazyLock<Histogram> = LazyLock::new(|| {
+    register_histogram!(
+        "learnbevy_queue_wait_seconds",
+        "How long requests wait for a turn to run a container",
+        vec![0.01, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0]
+    )
+    .unwrap()
+});
+
+static ARTIFACT_CACHE: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "learnbevy_artifact_cache_total",
+        "Stored artifacts by whether an identical one was already stored",
+        &["result"]
+    )
+    .unwrap()
+});
+
+static ARTIFACT_SIZE: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "learnbevy_artifact_bytes",
+        "The size of compiled artifacts",
+        &["kind"],
+        // 16KiB to 64MiB
+        exponential_buckets(16384.0, 2.0, 13).unwrap()
+    )
+    .unwrap()
+});
+
+static REAPED: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "learnbevy_reaped_total",
+        "Containers and instance directories that were left behind and removed",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+static RUNNING: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!("learnbevy_jobs_running", "The number of containers running").unwrap()
+});
+
+static QUEUED: LazyLock<IntGauge> = LazyLock::new(|| {
+    register_int_gauge!(
+        "learnbevy_jobs_queued",
+        "The number of requests waiting for a turn to run a container"
+    )
+    .unwrap()
+});
+
+/// Export the metrics in the Prometheus text format.
+pub async fn metrics() -> Response {
+    RUNNING.set(instances::running() as i64);
+    QUEUED.set(instances::queued() as i64);
+
+    let mut buffer = Vec::new();
+    let encoder = TextEncoder::new();
+    if let Err(error) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        error!("Failed to encode metrics: {error}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    let content_type = HeaderValue::from_str(encoder.format_type()).unwrap();
+    ([(CONTENT_TYPE, content_type)], buffer).into_response()
+}
+
+/// Record the duration and outcome of each request.
+pub async fn track(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let endpoint = endpoint(&request);
+    let (mut parts, body) = request.into_parts();
+    // Unknown versions would be a new label for every typo, so only known ones are kept.
+    let toolchain = Toolchain::from_request_parts(&mut parts, &()).await.ok();
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let (version, channel) = toolchain
+        .as_ref()
+        .map_or(("", ""), |t| (t.version.as_str(), t.channel.as_str()));
+    REQUEST_DURATION
+        .with_label_values(&[&endpoint, version, channel])
+        .observe(start.elapsed().as_secs_f64());
+    REQUESTS
+        .with_label_values(&[&endpoint, version, channel, outcome(&response)])
+        .inc();
+    response
+}
+
+/// The first segment of the route, such as `compile` for `/compile/:version/:channel`.
+pub fn endpoint(request: &Request) -> String {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| path.as_str().split('/').nth(1))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The outcome of a failed request, added to the extensions of its response.
+///
+/// Errors put it there themselves, and the coordinator takes it from the body of a worker's error.
+#[derive(Clone, Copy)]
+pub struct Outcome(&'static str);
+
+impl Outcome {
+    /// The outcome for a `kind` of [`crate::Error`].
+    pub fn of(kind: &str) -> Self {
+        Self(match kind {
+            "BadCode" => "bad_code",
+            "BadRequest" => "bad_request",
+            "NotFound" => "not_found",
+            "Unauthorized" => "unauthorized",
+            "Forbidden" => "forbidden",
+            "RateLimit" => "rate_limited",
+            "Unavailable" => "unavailable",
+            "Timeout" => "timeout",
+            "TooLarge" => "too_large",
+            _ => "internal",
+        })
+    }
+}
+
+/// The outcome of a request, from its [`Outcome`] or else its status.
+///
+/// Responses without an [`Outcome`] are rejected by axum, such as for a body that isn't JSON.
+fn outcome(response: &Response) -> &'static str {
+    match response.extensions().get::<Outcome>() {
+        Some(Outcome(outcome)) => outcome,
+        None if response.status().is_success() => "success",
+        None if response.status().is_client_error() => "rejected",
+        None => "internal",
+    }
+}
+
+pub fn observe_container_start(start: Instant) {
+    CONTAINER_START.observe(start.elapsed().as_secs_f64());
+}
+
+pub fn observe_queue_wait(start: Instant) {
+    QUEUE_WAIT.observe(start.elapsed().as_secs_f64());
+}
+
+pub fn observe_artifact(kind: &str, bytes: usize, cached: bool) {
+    ARTIFACT_SIZE
+        .with_label_values(&[kind])
+        .observe(bytes as f64);
+    ARTIFACT_CACHE
+        .with_label_values(&[if cached { "hit" } else { "miss" }])
+        .inc();
+}
+
+pub fn observe_reaped(kind: &str, count: usize) {
+    REAPED.with_label_values(&[kind]).inc_by(count as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn outcome_comes_from_the_error_not_the_status() {
+        let unavailable = Error::Unavailable.into_response();
+        let timeout = Error::Timeout.into_response();
+        assert_eq!(unavailable.status(), timeout.status());
+        assert_eq!(outcome(&unavailable), "unavailable");
+        assert_eq!(outcome(&timeout), "timeout");
+    }
+
+    #[test]
+    fn outcome_of_other_responses_uses_the_status() {
+        assert_eq!(outcome(&StatusCode::OK.into_response()), "success");
+        assert_eq!(
+            outcome(&StatusCode::UNPROCESSABLE_ENTITY.into_response()),
+            "rejected"
+        );
+    }
+}
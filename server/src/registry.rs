@@ -0,0 +1,405 @@
+use crate::{
+    compile,
+    config::{self, RegistrySource},
+    Error,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path},
+    http::request::Parts,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    io,
+    sync::{Arc, Mutex, RwLock},
+};
+use tokio::{fs, process};
+use tracing::{error, info, warn};
+
+/// Dependencies that aren't listed as crates avaliable to the user.
+const EXCLUDE_CRATES: &[&str] = &["playground_lib", "wasm-bindgen"];
+
+static REGISTRY: RwLock<Option<Arc<Registry>>> = RwLock::new(None);
+/// The output of inspecting each image, keyed by the image's ID so it's only done once.
+static PROBES: Mutex<BTreeMap<String, Probe>> = Mutex::new(BTreeMap::new());
+
+/// The tools an image can have, as the name of their binary.
+const TOOLS: &[&str] = &[
+    "cargo-clippy",
+    "bevy_lint",
+    "rustfmt",
+    "wasm-bindgen",
+    "wasm-opt",
+];
+
+/// Prints the Rust version, each of the [`TOOLS`] that exist, a separator and then the manifest.
+fn probe_script() -> String {
+    format!(
+        r#"
+rustc --version
+for tool in {}; do
+    command -v "$tool" > /dev/null && echo "$tool"
+done
+echo ---
+cat /playground/Cargo.toml
+"#,
+        TOOLS.join(" ")
+    )
+}
+
+#[derive(Clone)]
+struct Probe {
+    rustc: String,
+    tools: Vec<String>,
+    manifest: String,
+}
+
+/// The Bevy versions and Rust channels that code can be built with.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Registry {
+    pub versions: Vec<Version>,
+    default_version: Option<String>,
+    default_channel: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Version {
+    /// The name used in request paths, such as `0.16` or `main`.
+    pub name: String,
+    /// The version of the `bevy` crate, or `git` when using the repository.
+    bevy: Option<String>,
+    /// The edition the code is built with.
+    pub edition: Option<String>,
+    pub channels: Vec<Channel>,
+    crates: Vec<Crate>,
+    /// The date the version will be removed, as `YYYY-MM-DD`.
+    deprecated: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Channel {
+    pub name: String,
+    /// The output of `rustc --version` in the image.
+    rustc: Option<String>,
+    /// The tools in the image, or `None` if it hasn't been inspected.
+    pub tools: Option<Vec<String>>,
+    /// The ID of the image.
+    image_id: Option<String>,
+    /// The digest of the image, if it was pulled from a registry.
+    digest: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Crate {
+    name: String,
+    version: String,
+}
+
+/// Get the latest registry, this is empty until [`refresh`] has found the first version.
+pub fn get() -> Arc<Registry> {
+    REGISTRY
+        .read()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| Arc::new(Registry::default()))
+}
+
+impl Registry {
+    /// Add the deprecations and defaults from the config to the versions.
+    fn new(mut versions: Vec<Version>) -> Self {
+        let config = &config::get().registry;
+        for version in &mut versions {
+            version.deprecated = config.deprecations.get(&version.name).cloned();
+        }
+        Self {
+            versions,
+            default_version: config.default_version.clone(),
+            default_channel: config.default_channel.clone(),
+        }
+    }
+
+    pub fn contains(&self, version: &str, channel: &str) -> bool {
+        self.versions
+            .iter()
+            .filter(|v| v.name == version)
+            .any(|v| v.channels.iter().any(|c| c.name == channel))
+    }
+}
+
+/// Find the avaliable versions every [`config::RegistryConfig::refresh`].
+pub async fn refresh() {
+    let mut interval = tokio::time::interval(config::get().registry.refresh());
+    loop {
+        interval.tick().await;
+        // Taken first, as the versions can be published while they're discovered.
+        let previous = get().versions.len();
+        match discover().await {
+            Ok(registry) => {
+                if previous != registry.versions.len() {
+                    info!("Found {} versions", registry.versions.len());
+                }
+                *REGISTRY.write().unwrap() = Some(Arc::new(registry));
+            }
+            Err(error) => error!("Failed to find the avaliable versions: {error}"),
+        }
+    }
+}
+
+async fn discover() -> io::Result<Registry> {
+    let versions = match config::get().registry.source {
+        RegistrySource::Docker => from_images().await?,
+        RegistrySource::Manifests => from_manifests().await?,
+    };
+    Ok(Registry::new(versions))
+}
+
+/// Use versions that were found elsewhere, instead of [`refresh`]ing.
+pub fn update(versions: Vec<Version>) {
+    *REGISTRY.write().unwrap() = Some(Arc::new(Registry::new(versions)));
+}
+
+/// Combine the versions of several registries, with every channel that any of them have.
+pub fn merge<'a>(registries: impl IntoIterator<Item = &'a Registry>) -> Vec<Version> {
+    let mut versions: BTreeMap<String, Version> = BTreeMap::new();
+    for version in registries.into_iter().flat_map(|r| &r.versions) {
+        match versions.entry(version.name.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(version.clone());
+            }
+            Entry::Occupied(mut entry) => {
+                let channels = &mut entry.get_mut().channels;
+                for channel in &version.channels {
+                    if !channels.iter().any(|c| c.name == channel.name) {
+                        channels.push(channel.clone());
+                    }
+                }
+            }
+        }
+    }
+    versions.into_values().collect()
+}
+
+/// Find the local images that match the `image_template`.
+async fn from_images() -> io::Result<Vec<Version>> {
+    let config = config::get();
+    let output = process::Command::new("docker")
+        .args([
+            "image",
+            "ls",
+            "--format",
+            "{{.Repository}}:{{.Tag}} {{.ID}} {{.Digest}}",
+        ])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr)));
+    }
+
+    let mut versions: BTreeMap<String, Version> = BTreeMap::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let [image, id, digest] = line.split(' ').collect::<Vec<_>>()[..] else {
+            continue;
+        };
+        let Some((name, channel)) = config.parse_image(image) else {
+            continue;
+        };
+        let toolchain = Toolchain {
+            version: name.clone(),
+            channel: channel.clone(),
+        };
+        let Some(probe) = probe(image, id, toolchain).await? else {
+            continue;
+        };
+
+        // Each image has its own toolchain but the manifest is the same for every channel.
+        let version = versions
+            .entry(name.clone())
+            .or_insert_with(|| Version::new(name, &probe.manifest));
+        version.channels.push(Channel {
+            name: channel,
+            rustc: Some(probe.rustc),
+            tools: Some(probe.tools),
+            image_id: Some(id.to_string()),
+            digest: (digest != "<none>").then(|| digest.to_string()),
+        });
+        publish_found(&versions);
+    }
+    Ok(versions.into_values().collect())
+}
+
+/// Add the versions found so far to the ones that are published, so each image
+/// can be used as soon as it's probed instead of after every image is warmed.
+fn publish_found(found: &BTreeMap<String, Version>) {
+    let found = Registry {
+        versions: found.values().cloned().collect(),
+        ..Default::default()
+    };
+    update(merge([&found, &*get()]));
+}
+
+/// Get the Rust version and manifest of an image, and warm it up if `images.warm` is set.
+///
+/// Images that fail either aren't listed, and are tried again on the next refresh.
+async fn probe(image: &str, id: &str, toolchain: Toolchain) -> io::Result<Option<Probe>> {
+    if let Some(probe) = PROBES.lock().unwrap().get(id) {
+        return Ok(Some(probe.clone()));
+    }
+
+    let output = process::Command::new("docker")
+        .args(["run", "--rm", "--pull", "never", image])
+        .args(["sh", "-c", &probe_script()])
+        .output()
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        warn!("Failed to inspect {image}: {stderr}");
+        return Ok(None);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (head, manifest) = stdout.split_once("---\n").unwrap_or((&stdout, ""));
+    let mut lines = head.lines();
+    let probe = Probe {
+        rustc: lines.next().unwrap_or_default().trim().to_string(),
+        tools: lines
+            .map(str::trim)
+            .filter(|tool| TOOLS.contains(tool))
+            .map(str::to_string)
+            .collect(),
+        manifest: manifest.to_string(),
+    };
+    if config::get().images.warm {
+        info!("Warming up {image}");
+        if !compile::warm(toolchain).await {
+            warn!("Failed to warm up {image}, it won't be listed");
+            return Ok(None);
+        }
+    }
+    info!("Found {image} ({id}) with {}", probe.rustc);
+    PROBES.lock().unwrap().insert(id.to_string(), probe.clone());
+    Ok(Some(probe))
+}
+
+/// Read the manifests in the `manifests_dir`, each named `{version}.Cargo.toml`.
+async fn from_manifests() -> io::Result<Vec<Version>> {
+    let registry = &config::get().registry;
+    let Some(dir) = &registry.manifests_dir else {
+        return Ok(Vec::new());
+    };
+
+    let mut versions = Vec::new();
+    let mut entries = fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name();
+        let Some(name) = file_name
+            .to_str()
+            .and_then(|n| n.strip_suffix(".Cargo.toml"))
+        else {
+            continue;
+        };
+        let manifest = fs::read_to_string(entry.path()).await?;
+        let mut version = Version::new(name.to_string(), &manifest);
+        version.channels = registry
+            .channels
+            .iter()
+            .map(|name| Channel {
+                name: name.clone(),
+                rustc: None,
+                tools: None,
+                image_id: None,
+                digest: None,
+            })
+            .collect();
+        versions.push(version);
+    }
+    versions.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    Ok(versions)
+}
+
+impl Version {
+    fn new(name: String, manifest: &str) -> Self {
+        let mut crates = Vec::new();
+        let mut edition = None;
+        match manifest.parse::<toml::Table>() {
+            Ok(manifest) => {
+                edition = manifest
+                    .get("package")
+                    .and_then(|p| p.get("edition"))
+                    .and_then(|e| e.as_str())
+                    .map(str::to_string);
+                let dependencies = manifest.get("dependencies").and_then(|d| d.as_table());
+                for (name, value) in dependencies.into_iter().flatten() {
+                    if EXCLUDE_CRATES.contains(&name.as_str()) {
+                        continue;
+                    }
+                    let version = match value {
+                        toml::Value::String(version) => version.clone(),
+                        toml::Value::Table(table) if table.contains_key("git") => {
+                            String::from("git")
+                        }
+                        toml::Value::Table(table) => match table.get("version") {
+                            Some(toml::Value::String(version)) => version.clone(),
+                            _ => continue,
+                        },
+                        _ => continue,
+                    };
+                    crates.push(Crate {
+                        name: name.clone(),
+                        version,
+                    });
+                }
+            }
+            Err(error) => warn!("Failed to parse the manifest for {name}: {error}"),
+        }
+
+        Self {
+            bevy: crates
+                .iter()
+                .find(|c| c.name == "bevy")
+                .map(|c| c.version.clone()),
+            edition,
+            name,
+            channels: Vec::new(),
+            crates,
+            deprecated: None,
+        }
+    }
+}
+
+/// List the avaliable versions and channels.
+pub async fn versions() -> Response {
+    Json(&*get()).into_response()
+}
+
+/// The Bevy version and Rust channel of a request, taken from its path.
+///
+/// Requests for versions that aren't in the [`Registry`] fail with [`Error::NotFound`].
+#[derive(Debug)]
+pub(crate) struct Toolchain {
+    pub version: String,
+    pub channel: String,
+}
+
+impl Toolchain {
+    /// The name of the image to build with.
+    pub fn image(&self) -> String {
+        config::get().image(&self.version, &self.channel)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Toolchain {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path((version, channel)) = Path::<(String, String)>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| Error::NotFound)?;
+        if !get().contains(&version, &channel) {
+            return Err(Error::NotFound);
+        }
+        Ok(Self { version, channel })
+    }
+}
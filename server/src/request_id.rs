@@ -0,0 +1,53 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info_span, Instrument};
+
+/// The header a request ID is read from and returned in.
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// The longest request ID accepted from a client.
+const MAX_LENGTH: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The ID of the request being handled by the current task.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(String::clone).ok()
+}
+
+/// Give each request an ID, using the one sent by the client if there is one.
+///
+/// The ID is returned in the [`HEADER`] and everything logged while
+/// handling the request is inside a span with it, and the API key once it's known.
+pub async fn layer(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_LENGTH)
+        .map_or_else(|| format!("{:032x}", fastrand::u128(..)), str::to_string);
+
+    let span = info_span!(
+        "request",
+        id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        key = tracing::field::Empty,
+    );
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+
+    // The ID was either a valid header value or is made of hex digits.
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,38 @@
+use std::sync::LazyLock;
+use tokio::{signal, sync::watch};
+use tracing::{error, info};
+
+/// Whether the server has been asked to stop.
+static REQUESTED: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+
+/// Wait for SIGTERM or Ctrl+C, then tell everything waiting on [`requested`] to stop.
+pub async fn listen() {
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(error) => {
+                error!("Failed to listen for SIGTERM: {error}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = terminate => info!("Received SIGTERM, shutting down"),
+        _ = signal::ctrl_c() => info!("Received Ctrl+C, shutting down"),
+    }
+    REQUESTED.send_replace(true);
+}
+
+/// Wait until the server has been asked to stop.
+pub async fn requested() {
+    let mut receiver = REQUESTED.subscribe();
+    // The sender is never dropped so this can't fail.
+    let _ = receiver.wait_for(|requested| *requested).await;
+}
+
+/// Check if the server has been asked to stop.
+pub fn is_requested() -> bool {
+    *REQUESTED.borrow()
+}
@@ -0,0 +1,143 @@
+use rustc_demangle::demangle;
+use serde::Serialize;
+use std::{cmp::Reverse, collections::HashMap};
+use wasmparser::{KnownCustom, Name, Parser, Payload, TypeRef};
+
+/// The number of crates listed in a [`SizeReport`], the rest are grouped together.
+const MAX_CRATES: usize = 20;
+
+/// A breakdown of what makes up a compiled game.
+#[derive(Serialize)]
+pub struct SizeReport {
+    /// The size of the wasm before running `wasm-opt`.
+    before: usize,
+    /// The size of the wasm that is sent to the user.
+    after: usize,
+    /// The code size of each crate, largest first.
+    ///
+    /// This is measured before running `wasm-opt` as it removes the names.
+    crates: Vec<CrateSize>,
+}
+
+#[derive(Serialize)]
+struct CrateSize {
+    name: String,
+    bytes: usize,
+}
+
+/// Measure the code size of each crate using the name section of the `original` module.
+pub fn report(original: &[u8], optimized: &[u8]) -> wasmparser::Result<SizeReport> {
+    let mut imported_functions = 0;
+    let mut function_sizes = Vec::new();
+    let mut function_names = HashMap::new();
+    let mut data_size = 0;
+
+    for payload in Parser::new(0).parse_all(original) {
+        match payload? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if matches!(import?.ty, TypeRef::Func(_)) {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => function_sizes.push(body.range().len()),
+            Payload::DataSection(reader) => data_size += reader.range().len(),
+            Payload::CustomSection(reader) => {
+                let KnownCustom::Name(reader) = reader.as_known() else {
+                    continue;
+                };
+                for name in reader {
+                    if let Name::Function(map) = name? {
+                        for naming in map {
+                            let naming = naming?;
+                            function_names.insert(naming.index, naming.name);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut sizes: HashMap<String, usize> = HashMap::new();
+    // Function indices start after the imported functions.
+    for (index, size) in (imported_functions..).zip(function_sizes) {
+        let name = crate_name(function_names.get(&index).copied());
+        *sizes.entry(name).or_default() += size;
+    }
+    sizes.insert(String::from("[data]"), data_size);
+
+    let mut crates: Vec<CrateSize> = sizes
+        .into_iter()
+        .map(|(name, bytes)| CrateSize { name, bytes })
+        .collect();
+    crates.sort_unstable_by_key(|c| Reverse(c.bytes));
+    if crates.len() > MAX_CRATES {
+        let bytes = crates.drain(MAX_CRATES..).map(|c| c.bytes).sum();
+        crates.push(CrateSize {
+            name: String::from("[other]"),
+            bytes,
+        });
+    }
+
+    Ok(SizeReport {
+        before: original.len(),
+        after: optimized.len(),
+        crates,
+    })
+}
+
+/// Get the crate a function belongs to from its symbol.
+fn crate_name(symbol: Option<&str>) -> String {
+    let Some(symbol) = symbol else {
+        return String::from("[unnamed]");
+    };
+    let demangled = format!("{:#}", demangle(symbol));
+    // Trait impls look like `<bevy_ecs::world::World as core::fmt::Debug>::fmt`.
+    let path = demangled
+        .trim_start_matches(['<', '&', '*'])
+        .trim_start_matches("mut ")
+        .trim_start_matches("const ");
+    match path.split_once("::") {
+        Some((name, _)) if !name.contains([' ', '<', '(', '[']) => name.to_string(),
+        // Things like `memcpy` and the wasm-bindgen glue.
+        _ => String::from("[non-rust]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crate_name_of_mangled_symbols() {
+        assert_eq!(
+            crate_name(Some("_ZN8bevy_ecs5world5World3new17h0123456789abcdefE")),
+            "bevy_ecs"
+        );
+        assert_eq!(crate_name(Some("_RNvCs123_4game4main")), "game");
+    }
+
+    #[test]
+    fn crate_name_of_trait_impls() {
+        assert_eq!(
+            crate_name(Some("<bevy_ecs::world::World as core::fmt::Debug>::fmt")),
+            "bevy_ecs"
+        );
+        assert_eq!(
+            crate_name(Some("<&mut alloc::vec::Vec<u8> as std::io::Write>::write")),
+            "alloc"
+        );
+    }
+
+    #[test]
+    fn crate_name_of_other_functions() {
+        assert_eq!(crate_name(None), "[unnamed]");
+        assert_eq!(crate_name(Some("memcpy")), "[non-rust]");
+        assert_eq!(
+            crate_name(Some("<[T] as core::fmt::Debug>::fmt")),
+            "[non-rust]"
+        );
+    }
+}
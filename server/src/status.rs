@@ -0,0 +1,224 @@
+use crate::{
+    config::{self, Mode},
+    coordinator, instances, registry, shutdown,
+};
+use axum::{http::StatusCode, Json};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::{process, time};
+
+/// How long each readiness check can take before it counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the docker and image checks are reused for, so polling doesn't run docker each time.
+const CHECK_CACHE: Duration = Duration::from_secs(5);
+
+/// The last docker and image checks, and when they finished.
+static DOCKER_CHECKS: Mutex<Option<(Instant, Check, Check)>> = Mutex::new(None);
+
+/// Responds as long as the process is running.
+pub async fn health() -> &'static str {
+    "OK"
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    ready: bool,
+    /// The containers running, used by coordinators to find the least loaded worker.
+    running: usize,
+    queued: usize,
+    /// The number of containers that can run at once.
+    capacity: usize,
+    checks: BTreeMap<&'static str, Check>,
+}
+
+#[derive(Serialize, Clone)]
+struct Check {
+    ok: bool,
+    detail: String,
+}
+
+impl Check {
+    fn new(ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            ok,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Check if the server can take requests, responding with 503 if it can't.
+///
+/// Docker and the images are probed at most once every [`CHECK_CACHE`].
+pub async fn ready() -> (StatusCode, Json<Readiness>) {
+    let mut checks = match config::get().mode {
+        Mode::Worker => {
+            let (docker, images) = docker_checks().await;
+            BTreeMap::from([
+                ("docker", docker),
+                ("images", images),
+                ("queue", check_queue()),
+            ])
+        }
+        Mode::Coordinator => BTreeMap::from([("workers", check_workers())]),
+    };
+    checks.insert("shutdown", check_shutdown());
+
+    let ready = checks.values().all(|check| check.ok);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let readiness = Readiness {
+        ready,
+        running: instances::running(),
+        queued: instances::queued(),
+        capacity: config::get().max_concurrent_builds,
+        checks,
+    };
+    (status, Json(readiness))
+}
+
+/// Run [`check_docker`] and [`check_images`], or reuse their results if they're recent enough.
+///
+/// Requests that arrive while the checks run will run them too, which is fine as it's rare.
+async fn docker_checks() -> (Check, Check) {
+    if let Some((checked, docker, images)) = &*DOCKER_CHECKS.lock().unwrap() {
+        if checked.elapsed() < CHECK_CACHE {
+            return (docker.clone(), images.clone());
+        }
+    }
+    let (docker, images) = tokio::join!(check_docker(), check_images());
+    *DOCKER_CHECKS.lock().unwrap() = Some((Instant::now(), docker.clone(), images.clone()));
+    (docker, images)
+}
+
+async fn check_docker() -> Check {
+    let output = process::Command::new("docker")
+        .args(["info", "--format", "{{.ServerVersion}}"])
+        .kill_on_drop(true)
+        .output();
+    match time::timeout(CHECK_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => Check::new(
+            true,
+            format!("Docker {}", String::from_utf8_lossy(&output.stdout).trim()),
+        ),
+        Ok(Ok(output)) => Check::new(false, String::from_utf8_lossy(&output.stderr).trim()),
+        Ok(Err(error)) => Check::new(false, format!("Failed to run docker: {error}")),
+        Err(_) => Check::new(false, "Docker didn't respond in time"),
+    }
+}
+
+async fn check_images() -> Check {
+    let registry = registry::get();
+    let images: Vec<String> = registry
+        .versions
+        .iter()
+        .flat_map(|version| {
+            version
+                .channels
+                .iter()
+                .map(|channel| config::get().image(&version.name, &channel.name))
+        })
+        .collect();
+    if images.is_empty() {
+        return Check::new(false, "No versions are avaliable");
+    }
+
+    let output = process::Command::new("docker")
+        .args(["image", "inspect", "--format", "{{.Id}}"])
+        .args(&images)
+        .kill_on_drop(true)
+        .output();
+    match time::timeout(CHECK_TIMEOUT, output).await {
+        Ok(Ok(output)) if output.status.success() => {
+            Check::new(true, format!("{} images present", images.len()))
+        }
+        Ok(Ok(output)) => Check::new(false, String::from_utf8_lossy(&output.stderr).trim()),
+        Ok(Err(error)) => Check::new(false, format!("Failed to run docker: {error}")),
+        Err(_) => Check::new(false, "Docker didn't respond in time"),
+    }
+}
+
+fn check_queue() -> Check {
+    let (running, queued) = (instances::running(), instances::queued());
+    let max_queued = config::get().max_queued_builds;
+    Check::new(
+        queued < max_queued,
+        format!("{running} running, {queued} of {max_queued} queued"),
+    )
+}
+
+fn check_workers() -> Check {
+    let (ready, workers) = coordinator::available();
+    Check::new(ready > 0, format!("{ready} of {workers} workers ready"))
+}
+
+fn check_shutdown() -> Check {
+    if shutdown::is_requested() {
+        Check::new(false, "Shutting down")
+    } else {
+        Check::new(true, "Running")
+    }
+}
+
+#[derive(Serialize)]
+pub struct Capabilities {
+    endpoints: Vec<&'static str>,
+    versions: Vec<VersionCapabilities>,
+}
+
+#[derive(Serialize)]
+struct VersionCapabilities {
+    name: String,
+    channels: Vec<ChannelCapabilities>,
+}
+
+#[derive(Serialize)]
+struct ChannelCapabilities {
+    name: String,
+    /// The tools in the image, or `None` if they aren't known.
+    tools: Option<Vec<String>>,
+}
+
+/// List what this server can do.
+pub async fn capabilities() -> Json<Capabilities> {
+    let endpoints = &config::get().endpoints;
+    let endpoints = [
+        ("compile", endpoints.compile),
+        ("export", endpoints.export),
+        ("clippy", endpoints.clippy),
+        ("lint", endpoints.lint),
+        ("format", endpoints.format),
+        ("artifacts", endpoints.artifacts),
+        ("metrics", endpoints.metrics),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+
+    let versions = registry::get()
+        .versions
+        .iter()
+        .map(|version| VersionCapabilities {
+            name: version.name.clone(),
+            channels: version
+                .channels
+                .iter()
+                .map(|channel| ChannelCapabilities {
+                    name: channel.name.clone(),
+                    tools: channel.tools.clone(),
+                })
+                .collect(),
+        })
+        .collect();
+
+    Json(Capabilities {
+        endpoints,
+        versions,
+    })
+}
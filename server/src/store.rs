@@ -0,0 +1,176 @@
+use crate::{config, metrics, Error};
+use axum::{
+    extract::Path,
+    http::{
+        header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderMap, HeaderValue, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    io,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+use tokio::fs;
+use tracing::{error, info, warn};
+
+/// How long an artifact is kept after it was last produced.
+const TTL: Duration = Duration::from_secs(60 * 60);
+/// How often expired artifacts are removed.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The kinds of artifacts that can be stored, used as the file extension.
+#[derive(Clone, Copy)]
+pub enum Kind {
+    Wasm,
+    Js,
+}
+
+impl Kind {
+    fn extension(self) -> &'static str {
+        match self {
+            Kind::Wasm => "wasm",
+            Kind::Js => "js",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Kind::Wasm => "application/wasm",
+            Kind::Js => "text/javascript",
+        }
+    }
+
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "wasm" => Some(Kind::Wasm),
+            "js" => Some(Kind::Js),
+            _ => None,
+        }
+    }
+}
+
+/// A stored artifact, as given to the client.
+#[derive(Serialize)]
+pub struct Artifact {
+    /// The hex encoded SHA-256 of the artifact, for integrity checks.
+    pub sha256: String,
+    /// The path of the artifact on this server.
+    pub path: String,
+}
+
+/// The directory the artifacts are stored in.
+fn dir() -> PathBuf {
+    config::get().artifacts_dir.clone()
+}
+
+/// Store an artifact under its SHA-256.
+///
+/// Storing an artifact that already exists keeps it around for longer.
+pub async fn put(kind: Kind, bytes: &[u8]) -> io::Result<Artifact> {
+    let sha256 = format!("{:x}", Sha256::digest(bytes));
+    let name = format!("{sha256}.{}", kind.extension());
+    let path = dir().join(&name);
+
+    let cached = fs::try_exists(&path).await?;
+    if cached {
+        let file = fs::File::options().write(true).open(&path).await?;
+        file.into_std().await.set_modified(SystemTime::now())?;
+    } else {
+        // Write to a unique file first so a partial artifact is never served.
+        fs::create_dir_all(dir()).await?;
+        let temp = dir().join(format!("{name}.{}.tmp", fastrand::u64(..)));
+        fs::write(&temp, bytes).await?;
+        fs::rename(&temp, &path).await?;
+    }
+    metrics::observe_artifact(kind.extension(), bytes.len(), cached);
+
+    Ok(Artifact {
+        sha256,
+        path: format!("/artifacts/{name}"),
+    })
+}
+
+/// Serve a stored artifact.
+///
+/// The content of an artifact never changes so it can be cached forever,
+/// but it is only stored on the server for [`TTL`] after being produced.
+pub async fn get(Path(name): Path<String>, headers: HeaderMap) -> Result<Response, Error> {
+    let Some((sha256, kind)) = name
+        .split_once('.')
+        .filter(|(sha256, _)| sha256.len() == 64 && sha256.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|(sha256, extension)| Some((sha256, Kind::from_extension(extension)?)))
+    else {
+        return Err(Error::NotFound);
+    };
+
+    let etag = format!("\"{sha256}\"");
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(
+        CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=31536000, immutable"),
+    );
+    response_headers.insert(ETAG, HeaderValue::from_str(&etag).map_err(Error::internal)?);
+
+    if headers
+        .get(IF_NONE_MATCH)
+        .is_some_and(|value| value.as_bytes() == etag.as_bytes())
+    {
+        return Ok((StatusCode::NOT_MODIFIED, response_headers).into_response());
+    }
+
+    let bytes = match fs::read(dir().join(&name)).await {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Err(Error::NotFound),
+        Err(error) => return Err(error.into()),
+    };
+    response_headers.insert(CONTENT_TYPE, HeaderValue::from_static(kind.content_type()));
+    Ok((response_headers, bytes).into_response())
+}
+
+/// Remove expired artifacts every [`CLEANUP_INTERVAL`].
+pub async fn cleanup() {
+    let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        match remove_expired().await {
+            Ok(0) => {}
+            Ok(removed) => info!("Removed {removed} expired artifacts"),
+            Err(error) => error!("Failed to remove expired artifacts: {error}"),
+        }
+    }
+}
+
+async fn remove_expired() -> io::Result<usize> {
+    let mut entries = match fs::read_dir(dir()).await {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(error) => return Err(error),
+    };
+    let mut removed = 0;
+    while let Some(entry) = entries.next_entry().await? {
+        // An artifact that can't be removed is skipped so it doesn't hold up the others.
+        let path = entry.path();
+        let age = match entry
+            .metadata()
+            .await
+            .and_then(|metadata| metadata.modified())
+        {
+            Ok(modified) => modified.elapsed().unwrap_or_default(),
+            Err(error) => {
+                warn!("Failed to check the age of {}: {error}", path.display());
+                continue;
+            }
+        };
+        if age > TTL {
+            match fs::remove_file(&path).await {
+                Ok(()) => removed += 1,
+                Err(error) => warn!("Failed to remove {}: {error}", path.display()),
+            }
+        }
+    }
+    Ok(removed)
+}
@@ -0,0 +1,61 @@
+use crate::config::{LogFormat, LoggingConfig};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::error;
+use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+
+/// Set up logging, and trace exporting if an OTLP endpoint is configured.
+///
+/// The returned provider should be shut down before exiting so the last traces are sent.
+pub fn init(config: &LoggingConfig) -> Option<SdkTracerProvider> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.filter));
+
+    let (text, json) = match config.format {
+        LogFormat::Text => (Some(fmt::layer()), None),
+        LogFormat::Json => (None, Some(fmt::layer().json().with_span_list(true))),
+    };
+
+    let provider =
+        config.otlp_endpoint.as_ref().and_then(|endpoint| {
+            match SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint)
+                .build()
+            {
+                Ok(exporter) => Some(
+                    SdkTracerProvider::builder()
+                        .with_batch_exporter(exporter)
+                        .with_resource(
+                            Resource::builder()
+                                .with_service_name(config.service_name.clone())
+                                .build(),
+                        )
+                        .build(),
+                ),
+                Err(error) => {
+                    eprintln!("Failed to create the OTLP exporter: {error}");
+                    None
+                }
+            }
+        });
+    let otel = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(text)
+        .with(json)
+        .with(otel)
+        .init();
+    provider
+}
+
+/// Send any remaining traces.
+pub fn shutdown(provider: Option<SdkTracerProvider>) {
+    if let Some(Err(error)) = provider.map(|provider| provider.shutdown()) {
+        error!("Failed to shut down trace exporting: {error}");
+    }
+}
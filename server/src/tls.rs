@@ -0,0 +1,92 @@
+use crate::{config::TlsConfig, shutdown};
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use std::{fs::File, io, io::BufReader, sync::Arc, time::Duration};
+use tokio::{net::TcpListener, sync::mpsc, time};
+use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+use tracing::{debug, error};
+
+/// Load the certificate and key for serving over HTTPS.
+pub fn acceptor(config: &TlsConfig) -> io::Result<TlsAcceptor> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(File::open(&config.cert)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(File::open(&config.key)?))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no private key found"))?;
+
+    let mut server_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(io::Error::other)?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Serve the app over HTTPS, as [`axum::serve`] only supports HTTP.
+///
+/// Once [`shutdown::requested`] this stops accepting connections and
+/// waits for the open ones to finish their requests.
+pub async fn serve(listener: TcpListener, acceptor: TlsAcceptor, app: Router) -> io::Result<()> {
+    // Each connection holds a sender, so receiving fails once they've all closed.
+    let (open, mut closed) = mpsc::channel::<()>(1);
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = shutdown::requested() => break,
+        };
+        let (stream, address) = match accepted {
+            Ok(accepted) => accepted,
+            Err(error) => {
+                // Like `axum::serve`, errors such as running out of file descriptors are
+                // waited out instead of stopping the server.
+                if !is_connection_error(&error) {
+                    error!("Failed to accept a connection: {error}");
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let service = TowerToHyperService::new(app.clone().layer(Extension(ConnectInfo(address))));
+        let open = open.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(error) => {
+                    debug!("TLS handshake with {address} failed: {error}");
+                    return;
+                }
+            };
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            tokio::pin!(connection);
+            let result = tokio::select! {
+                result = connection.as_mut() => result,
+                _ = shutdown::requested() => {
+                    connection.as_mut().graceful_shutdown();
+                    connection.await
+                }
+            };
+            if let Err(error) = result {
+                debug!("Connection with {address} failed: {error}");
+            }
+            drop(open);
+        });
+    }
+    drop(open);
+    let _ = closed.recv().await;
+    Ok(())
+}
+
+/// Errors that only affect the connection being accepted.
+fn is_connection_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
@@ -1,4 +1,5 @@
-const CONST_CHANNELS = ["stable", "nightly"] as const;
-export type Channel = (typeof CONST_CHANNELS)[number];
+/** Used until the compile server's channels are known, see `fetchRegistry`. */
+const FALLBACK_CHANNELS = ["stable", "nightly"];
+export type Channel = string;
 export const DEFAULT_CHANNEL: Channel = "nightly";
-export const CHANNELS = CONST_CHANNELS as unknown as Channel[];
+export const CHANNELS: Channel[] = FALLBACK_CHANNELS;
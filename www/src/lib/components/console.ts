@@ -11,5 +11,17 @@ type ConsoleLog = {
     level: LogLevel;
     location: string;
     message: string;
+    frame?: number;
+    fields?: Record<string, string>;
+};
+/** A tracing event forwarded by `playground_lib`. */
+export type TraceRecord = {
+    level: LogLevel;
+    target: string;
+    location: string | null;
+    frame: number;
+    message: string;
+    fields: Record<string, string>;
+    spans: { name: string; fields: Record<string, string> }[];
 };
 export const consoleItems = writable<ConsoleItem[]>([]);
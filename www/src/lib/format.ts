@@ -3,11 +3,14 @@ import { consoleItems } from "$lib/components/console";
 import { get } from "svelte/store";
 import { editorCode } from "./components/editor";
 import { env } from "$env/dynamic/public";
+import type { Version } from "$lib/versions";
+import type { Channel } from "$lib/channels";
 
-export async function formatCode() {
+/** Format the editor's code with the rustfmt and edition of the version. */
+export async function formatCode(version: Version, channel: Channel) {
     const promise = new Promise(async (resolve, reject) => {
         const host = env.PUBLIC_COMPILE_HOST ?? "https://slc.compute.learnbevy.com";
-        const url = `${host}/format`;
+        const url = `${host}/format/${version}/${channel}`;
         const res = await fetch(url, {
             method: "POST",
             body: JSON.stringify({ code: get(editorCode) }),
@@ -34,12 +37,13 @@ export async function formatCode() {
         error: (e) => {
             const err = e as FmtError;
             if (err.kind === "BadCode") return "Code could not be formatted";
+            if (err.kind === "BadRequest") return err.message;
             return "Something went wrong on our end";
         },
     });
 }
 
-type FmtError = FmtUserError | FmtServerError;
+type FmtError = FmtUserError | FmtBadRequestError | FmtServerError;
 
 type FmtSuccess = {
     formatted_code: string;
@@ -50,6 +54,11 @@ type FmtUserError = {
     stderr: string;
 };
 
+type FmtBadRequestError = {
+    kind: "BadRequest";
+    message: string;
+};
+
 type FmtServerError = {
     kind: "Internal";
 };
@@ -7,6 +7,38 @@ type CompileArgs = {
     version: Version;
     channel: Channel;
     parentId: string;
+    onPanic?: (report: PanicReport) => void;
+    /** The seed for the app's `PlaygroundRng`, a random one is used if not given. */
+    seed?: bigint;
+    /** Report the system that was running in panics, which costs a little every frame. */
+    systemSpans?: boolean;
+    /** Record the input of this run, see `wasm.take_input_recording()`. */
+    recordInput?: boolean;
+    /** A recording to replay in place of real input. */
+    inputReplay?: string;
+    /** Receives frames requested with `wasm.screenshot()` or `wasm.capture_frames()`. */
+    onFrame?: (frame: CapturedFrame) => void;
+    profile?: BuildProfile;
+    /** Run `wasm-opt` on the output at this level. */
+    wasmOpt?: "1" | "2" | "3" | "4" | "s" | "z";
+};
+
+export type BuildProfile = "release-size" | "release-speed" | "debug-with-assertions";
+
+/** A frame captured by `playground_lib`, as RGBA8 pixels. */
+export type CapturedFrame = {
+    index: number;
+    width: number;
+    height: number;
+    rgba: Uint8Array;
+};
+
+/** A panic reported by `playground_lib`'s panic hook. */
+export type PanicReport = {
+    message: string;
+    location: { file: string; line: number; column: number; user_code: boolean } | null;
+    system: string | null;
+    backtrace: string[] | null;
 };
 
 export async function play(args: CompileArgs): Promise<PlayResponse> {
@@ -20,9 +52,15 @@ export async function play(args: CompileArgs): Promise<PlayResponse> {
             code: args.code,
             version: args.version,
             channel: args.channel,
+            system_spans: args.systemSpans,
+            profile: args.profile,
+            wasm_opt: args.wasmOpt,
+            // They're loaded from the stored artifacts, which can be cached
+            artifacts_only: true,
         }),
         headers: {
             "Content-Type": "application/json",
+            Accept: ARTIFACTS_CONTENT_TYPE,
         },
     });
 
@@ -47,8 +85,29 @@ export async function play(args: CompileArgs): Promise<PlayResponse> {
             case "BadCode":
                 msg = "The code failed to build";
                 break;
+            case "BadRequest":
+                msg = error.message;
+                break;
+            case "Unauthorized":
+                msg = "The server requires an API key";
+                break;
+            case "Forbidden":
+                msg = "Your API key can't be used for this";
+                break;
+            case "Unavailable":
+                msg = "No servers are available right now, please try again later";
+                break;
+            case "Timeout":
+                msg = "The server is busy or the build took too long, please try again";
+                break;
+            case "TooLarge":
+                msg = "The code is too large";
+                break;
             case "Internal":
                 msg = "An internal server error occurred";
+                if (error.request_id) {
+                    msg += ` (request ${error.request_id})`;
+                }
                 break;
         }
         return {
@@ -58,33 +117,96 @@ export async function play(args: CompileArgs): Promise<PlayResponse> {
         };
     }
 
-    const wasmSize = parseInt(res.headers.get("wasm-content-length")!);
-    const jsSize = parseInt(res.headers.get("js-content-length")!);
+    const { wasm, js, stderr, sizeReport, stored } = await readArtifacts(res);
 
-    const body = await res.blob();
+    // Older servers only send the wasm and JS in the response, as do those that don't serve artifacts
+    let files: { wasm: Blob; js: Blob; esModule: boolean } | null = null;
+    if (stored) {
+        const [storedWasm, storedJs] = await Promise.all([
+            fetchArtifact(host, stored.wasm),
+            fetchArtifact(host, stored.js),
+        ]);
+        if (storedWasm && storedJs) {
+            files = { wasm: storedWasm, js: storedJs, esModule: true };
+        }
+    }
+    if (!files && wasm && js) {
+        files = { wasm, js, esModule: false };
+    }
+    if (!files) {
+        return { kind: "Failed", message: "The build was corrupted, please try again", stderr: null };
+    }
+
+    // Compiling the wasm up front lets the app be restarted without fetching it again
+    const build: LoadedBuild = {
+        js: await files.js.text(),
+        esModule: files.esModule,
+        module: await WebAssembly.compile(await files.wasm.arrayBuffer()),
+        stderr: await stderr.text(),
+        sizeReport,
+    };
+    return start(build, args);
+}
 
-    // Split the response in to its parts
-    const wasm = body.slice(0, wasmSize, "application/wasm");
-    const js = body.slice(wasmSize, wasmSize + jsSize, "application/javascript");
-    const stderr = body.slice(wasmSize + jsSize, -1, "text/plain");
-
-    // Convert js and stderr from bytes to strings
-    const jsText = await js.text();
-    const stderrText = await stderr.text();
-
-    // For some reason the js will never return so I have to use this object to get the nessessery values out :(
-    let refObj: any = new Object();
-    const AsyncFunction: any = async function () {}.constructor;
-    const load = new AsyncFunction("wasm_blob", "ref_obj", jsText);
-    await load(wasm, refObj).catch((error: { message: string }) => {
-        if (
-            !error.message.startsWith(
-                "Using exceptions for control flow, don't mind me. This isn't actually an error!"
-            )
-        ) {
-            throw error;
+/** Fetch a stored artifact, or `null` if it's missing or its hash doesn't match. */
+async function fetchArtifact(host: string, artifact: StoredArtifact): Promise<Blob | null> {
+    const res = await fetch(`${host}${artifact.path}`).catch(() => null);
+    if (!res?.ok) return null;
+    const blob = await res.blob();
+    // Make sure the build wasn't corrupted on the way
+    return (await sha256(blob)) === artifact.sha256 ? blob : null;
+}
+
+/** Winit uses an exception to escape the event loop once the app has started. */
+function ignoreControlFlow(error: { message: string }) {
+    if (
+        !error.message.startsWith(
+            "Using exceptions for control flow, don't mind me. This isn't actually an error!"
+        )
+    ) {
+        throw error;
+    }
+}
+
+/**
+ * Start a build that was already loaded, with a fresh instance of its wasm module.
+ *
+ * Winit can only create one event loop per instance, so this is how an app is restarted.
+ * The previous app should be exited and its canvas removed first.
+ */
+export async function start(
+    build: LoadedBuild,
+    args: Omit<CompileArgs, "code" | "version" | "channel">
+): Promise<Success | ConsoleOnly> {
+    // Called by `playground_lib` when the app panics
+    (window as any).learnbevy = {
+        onPanic: (json: string) => args.onPanic?.(JSON.parse(json)),
+        seed: () => args.seed,
+        recordInput: () => args.recordInput ?? false,
+        inputReplay: () => args.inputReplay,
+        onFrame: (index: number, width: number, height: number, rgba: Uint8Array) =>
+            args.onFrame?.({ index, width, height, rgba }),
+    };
+
+    let wasm: any;
+    if (build.esModule) {
+        // Each run imports its own copy of the JS, as it holds on to the instance it started
+        const url = URL.createObjectURL(new Blob([build.js], { type: "text/javascript" }));
+        try {
+            const bindings = await import(/* @vite-ignore */ url);
+            await bindings.default({ module_or_path: build.module }).catch(ignoreControlFlow);
+            wasm = bindings;
+        } finally {
+            URL.revokeObjectURL(url);
         }
-    });
+    } else {
+        // For some reason the js will never return so I have to use this object to get the nessessery values out :(
+        let refObj: any = new Object();
+        const AsyncFunction: any = async function () {}.constructor;
+        const load = new AsyncFunction("wasm_blob", "ref_obj", build.js);
+        await load(build.module, refObj).catch(ignoreControlFlow);
+        wasm = refObj.bindings ?? refObj.wasm;
+    }
 
     // Get the spawned canvas element if it exists
     const gameCanvas: HTMLCanvasElement | null =
@@ -93,7 +215,13 @@ export async function play(args: CompileArgs): Promise<PlayResponse> {
 
     // Return if no canvas was spawned
     if (!gameCanvas) {
-        return { kind: "ConsoleOnly", wasm: refObj.wasm, stderr: stderrText };
+        return {
+            kind: "ConsoleOnly",
+            wasm,
+            stderr: build.stderr,
+            sizeReport: build.sizeReport,
+            build,
+        };
     }
     // Set the canvas's parent to the element with the given parentId
     const parent = document.getElementById(args.parentId)!;
@@ -107,20 +235,117 @@ export async function play(args: CompileArgs): Promise<PlayResponse> {
     gameCanvas.style.height = `${parent.clientWidth * (9 / 16)}px`;
     gameCanvas.style.borderRadius = "0.5rem";
 
-    return { kind: "Success", gameCanvas, wasm: refObj.wasm, stderr: stderrText };
+    return {
+        kind: "Success",
+        gameCanvas,
+        wasm,
+        stderr: build.stderr,
+        sizeReport: build.sizeReport,
+        build,
+    };
+}
+
+/** The versioned framed format the server sends artifacts in, see `server/src/artifacts.rs`. */
+const ARTIFACTS_CONTENT_TYPE = "application/vnd.learnbevy.artifacts.v1";
+
+type ArtifactsManifest = {
+    version: number;
+    parts: { name: string; content_type: string; length: number }[];
+};
+
+type Artifacts = {
+    /** Left out when only the stored artifacts were asked for. */
+    wasm: Blob | null;
+    js: Blob | null;
+    stderr: Blob;
+    sizeReport: SizeReport | null;
+    stored: StoredArtifacts | null;
+};
+
+/** The wasm and JS as stored on the server by their SHA-256, see `server/src/store.rs`. */
+export type StoredArtifacts = {
+    wasm: StoredArtifact;
+    js: StoredArtifact;
+};
+
+/** The `path` is relative to the compile host and can be cached forever. */
+export type StoredArtifact = { sha256: string; path: string };
+
+async function sha256(blob: Blob): Promise<string> {
+    const hash = await crypto.subtle.digest("SHA-256", await blob.arrayBuffer());
+    return Array.from(new Uint8Array(hash), (b) => b.toString(16).padStart(2, "0")).join("");
+}
+
+/** Split a compile response in to its parts. */
+async function readArtifacts(res: Response): Promise<Artifacts> {
+    const body = await res.blob();
+
+    // Older servers concatenate the parts and give their lengths in headers
+    if (res.headers.get("Content-Type") !== ARTIFACTS_CONTENT_TYPE) {
+        const wasmSize = parseInt(res.headers.get("wasm-content-length")!);
+        const jsSize = parseInt(res.headers.get("js-content-length")!);
+        const sizeReport = res.headers.get("size-report");
+        const stored = res.headers.get("artifacts");
+        return {
+            wasm: body.slice(0, wasmSize, "application/wasm"),
+            js: body.slice(wasmSize, wasmSize + jsSize, "application/javascript"),
+            stderr: body.slice(wasmSize + jsSize, body.size, "text/plain"),
+            sizeReport: sizeReport ? JSON.parse(sizeReport) : null,
+            stored: stored ? JSON.parse(stored) : null,
+        };
+    }
+
+    const manifestSize = new DataView(await body.slice(0, 4).arrayBuffer()).getUint32(0, true);
+    const manifest: ArtifactsManifest = JSON.parse(await body.slice(4, 4 + manifestSize).text());
+    const parts = new Map<string, Blob>();
+    let offset = 4 + manifestSize;
+    for (const part of manifest.parts) {
+        parts.set(part.name, body.slice(offset, offset + part.length, part.content_type));
+        offset += part.length;
+    }
+    const sizeReport = parts.get("size-report");
+    const stored = parts.get("artifacts");
+    return {
+        wasm: parts.get("wasm") ?? null,
+        js: parts.get("js") ?? null,
+        stderr: parts.get("stderr") ?? new Blob(),
+        sizeReport: sizeReport ? JSON.parse(await sizeReport.text()) : null,
+        stored: stored ? JSON.parse(await stored.text()) : null,
+    };
 }
 
+/** What makes up the size of a compiled game, see `server/src/size.rs`. */
+export type SizeReport = {
+    before: number;
+    after: number;
+    crates: { name: string; bytes: number }[];
+};
+
+/** A build that has been fetched and compiled, which can be started again with {@link start}. */
+export type LoadedBuild = {
+    js: string;
+    /** If the JS is the ES module from wasm-bindgen, rather than the playground's function body. */
+    esModule: boolean;
+    module: WebAssembly.Module;
+    stderr: string;
+    sizeReport: SizeReport | null;
+};
+
 type PlayResponse = Success | ConsoleOnly | Failed;
 type Success = {
     kind: "Success";
     gameCanvas: HTMLCanvasElement;
     wasm: any;
     stderr: string;
+    sizeReport: SizeReport | null;
+    build: LoadedBuild;
 };
 type ConsoleOnly = {
     kind: "ConsoleOnly";
     wasm: any;
     stderr: string;
+    sizeReport: SizeReport | null;
+    build: LoadedBuild;
 };
 type Failed = {
     kind: "Failed";
@@ -134,6 +359,12 @@ type BcaError =
     | ActiveRequestExistsError
     | DisallowedWordError
     | BuildFailedError
+    | BadRequestError
+    | UnauthorizedError
+    | ForbiddenError
+    | UnavailableError
+    | TimeoutError
+    | TooLargeError
     | InternalError;
 type RateLimitError = {
     kind: "RateLimit";
@@ -153,6 +384,27 @@ type BuildFailedError = {
     kind: "BadCode";
     stderr: string;
 };
+type BadRequestError = {
+    kind: "BadRequest";
+    message: string;
+};
+type UnauthorizedError = {
+    kind: "Unauthorized";
+};
+type ForbiddenError = {
+    kind: "Forbidden";
+};
+type UnavailableError = {
+    kind: "Unavailable";
+};
+type TimeoutError = {
+    kind: "Timeout";
+};
+type TooLargeError = {
+    kind: "TooLarge";
+};
 type InternalError = {
     kind: "Internal";
+    /** The ID to find the request in the server's logs. */
+    request_id?: string;
 };
@@ -1,4 +1,35 @@
-const CONST_VERSIONS = ["main", "0.16"] as const;
-export type Version = (typeof CONST_VERSIONS)[number];
+import { env } from "$env/dynamic/public";
+
+/** Used until the compile server's versions are known, or if it can't be reached. */
+const FALLBACK_VERSIONS = ["main", "0.16"];
+export type Version = string;
 export const DEFAULT_VERSION: Version = "0.16";
-export const VERSIONS = CONST_VERSIONS as unknown as Version[];
+export const VERSIONS: Version[] = FALLBACK_VERSIONS;
+
+/** The versions and channels the compile server can build with, from its `/versions` endpoint. */
+export type Registry = {
+    versions: VersionInfo[];
+    default_version: string | null;
+    default_channel: string | null;
+};
+
+export type VersionInfo = {
+    name: Version;
+    /** The version of the `bevy` crate, or `git` when using the repository. */
+    bevy: string | null;
+    channels: { name: string; rustc: string | null }[];
+    crates: { name: string; version: string }[];
+    /** The date the version will be removed, as `YYYY-MM-DD`. */
+    deprecated: string | null;
+};
+
+let registry: Promise<Registry | null> | null = null;
+
+/** Fetch the versions avaliable on the compile server, only requested once per page load. */
+export function fetchRegistry(): Promise<Registry | null> {
+    const host = env.PUBLIC_COMPILE_HOST ?? "https://slc.compute.learnbevy.com";
+    registry ??= fetch(`${host}/versions`)
+        .then((res) => (res.ok ? res.json() : null))
+        .catch(() => null);
+    return registry;
+}